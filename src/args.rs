@@ -1,5 +1,6 @@
 use {
     crate::{
+        exec::OutputStyle,
         plan::ExecutionPlan,
         workflow::Workflow,
     },
@@ -61,6 +62,7 @@ pub(crate) enum ManualFormat {
 }
 
 #[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) enum Format {
     YAML,
     #[cfg(feature = "format+json")]
@@ -73,6 +75,12 @@ pub(crate) enum Format {
     },
 }
 
+#[derive(Debug)]
+pub(crate) enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
 #[derive(Debug)]
 pub(crate) enum CommandListFormat {
     Standard(Format),
@@ -171,7 +179,7 @@ impl Nodes {
                 let re = fancy_regex::Regex::new(&v)?;
                 let mut hs = HashSet::<String>::new();
                 for node in wf.nodes.keys() {
-                    if re.is_match(&node)? {
+                    if re.is_match(node)? {
                         hs.insert(node.clone());
                     }
                 }
@@ -181,15 +189,44 @@ impl Nodes {
     }
 }
 
+#[derive(Debug)]
+pub(crate) struct ExecutionArgs {
+    pub workers: usize,
+    pub output: OutputStyle,
+    pub no_stdout: bool,
+    pub no_stderr: bool,
+    pub keep_going: bool,
+    pub events: Option<String>,
+    pub junit: Option<String>,
+    pub resume: bool,
+    pub dry_run: bool,
+    pub state_dir: String,
+}
+
 #[derive(Debug)]
 pub(crate) enum WorkflowCommand {
     Schema,
+    Lint {
+        workflow: String,
+    },
     Init {
         template: WorkflowInitTemplate,
         output: WorkflowInitOutput,
     },
 }
 
+#[derive(Debug)]
+pub(crate) enum EventsCommand {
+    Schema,
+}
+
+#[derive(Debug)]
+pub(crate) enum CacheCommand {
+    List,
+    Prune { older_than: std::time::Duration },
+    Clear,
+}
+
 #[derive(Debug)]
 pub(crate) enum Command {
     Manual {
@@ -199,13 +236,23 @@ pub(crate) enum Command {
     Autocomplete {
         path: String,
         shell: clap_complete::Shell,
+        workflow: Option<String>,
     },
     Workflow(WorkflowCommand),
+    Events(EventsCommand),
+    Cache {
+        state_dir: String,
+        command: CacheCommand,
+    },
     Execute {
         plan: ExecutionPlan,
-        workers: usize,
-        no_stdout: bool,
-        no_stderr: bool,
+        execution: ExecutionArgs,
+    },
+    Run {
+        workflow: String,
+        nodes: Nodes,
+        args: HashMap<String, String>,
+        execution: ExecutionArgs,
     },
     Plan {
         workflow: String,
@@ -222,6 +269,22 @@ pub(crate) enum Command {
         nodes: Nodes,
         format: Format,
     },
+    Dependents {
+        workflow: String,
+        nodes: HashSet<String>,
+        format: Format,
+    },
+    Explain {
+        workflow: String,
+        targets: Vec<String>,
+        node: String,
+    },
+    Graph {
+        workflow: String,
+        nodes: Nodes,
+        format: GraphFormat,
+        details: bool,
+    },
     Watch {
         filter: String,
         root: String,
@@ -251,7 +314,77 @@ impl ClapArgumentLoader {
         output_formats.extend(["ron", "ron+p"]);
         // strip format modifiers ("+\w")
         let input_formats = output_formats.iter().filter(|v| !v.ends_with("+p")).collect_vec();
-        assert!(output_formats.len() > 0);
+        assert!(!output_formats.is_empty());
+
+        // shared between all commands that access the local state
+        let state_dir_arg = Arg::new("state-dir")
+            .long("state-dir")
+            .help("The directory that holds fingerprints, cached outputs and the progress of executions.")
+            .default_value(crate::state::DEFAULT_STATE_DIR);
+
+        // shared between all commands that execute a plan
+        let execution_args = [
+            Arg::new("workers")
+                .short('w')
+                .long("workers")
+                .help("Defines how many worker threads are created in the OS thread pool.")
+                .default_value("1"),
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .help(
+                    "Defines how the output of the tasks is written. \"prefix\" prefixes every line with the node \
+                     name and matrix cell. \"grouped\" buffers the output (STDOUT and STDERR) of every task and \
+                     prints it as one block once the task finished (to STDERR if only STDERR is shown).",
+                )
+                .value_parser(["inherit", "prefix", "prefix+color", "grouped"])
+                .default_value("inherit"),
+            Arg::new("no-stdout")
+                .long("no-stdout")
+                .help(
+                    "Disables any output to STDOUT. Useful for preventing leakage of secrets and keeping the logs \
+                     clean.",
+                )
+                .num_args(0),
+            Arg::new("no-stderr")
+                .long("no-stderr")
+                .help(
+                    "Disables any output to STDERR. Useful for preventing leakage of secrets and keeping the logs \
+                     clean.",
+                )
+                .num_args(0),
+            Arg::new("keep-going")
+                .short('k')
+                .long("keep-going")
+                .help(
+                    "Keeps executing all nodes that do not depend on a failed node instead of terminating all running \
+                     tasks on the first failure. All failures are reported at the end.",
+                )
+                .num_args(0),
+            Arg::new("events").long("events").help(
+                "Writes newline delimited JSON events about the execution to the given file. \"-\" writes to STDOUT \
+                 and the output of the tasks to STDERR. The schema is rendered by \"neomake events schema\".",
+            ),
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help(
+                    "Prints every resolved task (shell, workdir, merged env vars and command) in stage order without \
+                     executing anything. Values of env vars that look like secrets are masked.",
+                )
+                .num_args(0),
+            Arg::new("resume")
+                .long("resume")
+                .help(
+                    "Skips all tasks that succeeded in the last execution of the same plan. The progress of every \
+                     execution is recorded in \"runs\" in the state directory until the plan succeeds.",
+                )
+                .num_args(0),
+            state_dir_arg.clone(),
+            Arg::new("junit").long("junit").help(
+                "Writes a JUnit XML report with one testsuite per node and one testcase per matrix entry and task to \
+                 the given file.",
+            ),
+        ];
 
         clap::Command::new("neomake")
             .version(env!("CARGO_PKG_VERSION"))
@@ -286,6 +419,11 @@ impl ClapArgumentLoader {
                             .long("shell")
                             .value_parser(["bash", "zsh", "fish", "elvish", "powershell"])
                             .required(true),
+                    )
+                    .arg(
+                        clap::Arg::new("workflow")
+                            .long("workflow")
+                            .help("Completes the arguments declared in this workflow file."),
                     ),
             )
             .subcommand(
@@ -310,12 +448,48 @@ impl ClapArgumentLoader {
                                     .default_value("./neomake.yaml"),
                             ),
                     )
-                    .subcommand(clap::Command::new("schema").about("Renders the workflow schema to STDOUT.")),
+                    .subcommand(clap::Command::new("schema").about("Renders the workflow schema to STDOUT."))
+                    .subcommand(
+                        clap::Command::new("lint")
+                            .about("Validates the workflow and reports all problems. Fails if there are any.")
+                            .arg(
+                                Arg::new("workflow")
+                                    .long("workflow")
+                                    .help("The workflow file to use.")
+                                    .default_value("./neomake.yaml"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                clap::Command::new("events")
+                    .about("Execution event related subcommands.")
+                    .subcommand(clap::Command::new("schema").about("Renders the execution event schema to STDOUT.")),
+            )
+            .subcommand(
+                clap::Command::new("cache")
+                    .about("Local artifact cache related subcommands.")
+                    .arg(state_dir_arg.global(true))
+                    .subcommand(
+                        clap::Command::new("ls")
+                            .about("Lists all cache entries.")
+                            .visible_aliases(["list"]),
+                    )
+                    .subcommand(
+                        clap::Command::new("prune")
+                            .about("Removes old cache entries and all files that are not referenced anymore.")
+                            .arg(
+                                Arg::new("older-than")
+                                    .long("older-than")
+                                    .help("Removes entries older than the given duration (e.g. \"12h\", \"7d\").")
+                                    .default_value("7d"),
+                            ),
+                    )
+                    .subcommand(clap::Command::new("clear").about("Removes the entire cache.")),
             )
             .subcommand(
                 clap::Command::new("plan")
                     .about("Creates an execution plan.")
-                    .visible_aliases(&["p"])
+                    .visible_aliases(["p"])
                     .arg(
                         Arg::new("workflow")
                             .long("workflow")
@@ -358,7 +532,7 @@ impl ClapArgumentLoader {
             .subcommand(
                 clap::Command::new("execute")
                     .about("Executes an execution plan.")
-                    .visible_aliases(&["exec", "x"])
+                    .visible_aliases(["exec", "x"])
                     .arg(
                         Arg::new("format")
                             .short('f')
@@ -367,36 +541,48 @@ impl ClapArgumentLoader {
                             .value_parser(input_formats.clone())
                             .default_value(*input_formats.first().unwrap()),
                     )
+                    .args(execution_args.clone()),
+            )
+            .subcommand(
+                clap::Command::new("run")
+                    .about("Creates an execution plan and executes it right away.")
+                    .visible_aliases(["r"])
+                    .arg(
+                        Arg::new("workflow")
+                            .long("workflow")
+                            .help("The workflow file to use.")
+                            .default_value("./neomake.yaml"),
+                    )
                     .arg(
-                        Arg::new("workers")
-                            .short('w')
-                            .long("workers")
-                            .help("Defines how many worker threads are created in the OS thread pool.")
-                            .default_value("1"),
+                        Arg::new("node")
+                            .short('n')
+                            .long("node")
+                            .action(ArgAction::Append)
+                            .conflicts_with("regex")
+                            .required_unless_present("regex")
+                            .help("Adding a node to the plan."),
                     )
                     .arg(
-                        Arg::new("no-stdout")
-                            .long("no-stdout")
-                            .help(
-                                "Disables any output to STDOUT. Useful for preventing leakage of secrets and keeping \
-                                 the logs clean.",
-                            )
-                            .num_args(0),
+                        Arg::new("regex")
+                            .short('r')
+                            .long("regex")
+                            .conflicts_with("node")
+                            .required_unless_present("node")
+                            .help("Adding a node to the plan."),
                     )
                     .arg(
-                        Arg::new("no-stderr")
-                            .long("no-stderr")
-                            .help(
-                                "Disables any output to STDERR. Useful for preventing leakage of secrets and keeping \
-                                 the logs clean.",
-                            )
-                            .num_args(0),
-                    ),
+                        Arg::new("arg")
+                            .short('a')
+                            .long("arg")
+                            .action(ArgAction::Append)
+                            .help("Specifies a value for handlebars placeholders."),
+                    )
+                    .args(execution_args),
             )
             .subcommand(
                 clap::Command::new("describe")
                     .about("Describes which nodes are executed in which stages.")
-                    .visible_aliases(&["desc", "d"])
+                    .visible_aliases(["desc", "d"])
                     .arg(
                         Arg::new("workflow")
                             .long("workflow")
@@ -429,10 +615,97 @@ impl ClapArgumentLoader {
                             .default_value(output_formats.first().unwrap()),
                     ),
             )
+            .subcommand(
+                clap::Command::new("dependents")
+                    .about("Lists all nodes that (transitively) depend on the given nodes.")
+                    .arg(
+                        Arg::new("workflow")
+                            .long("workflow")
+                            .help("The workflow file to use.")
+                            .default_value("./neomake.yaml"),
+                    )
+                    .arg(
+                        Arg::new("node")
+                            .short('n')
+                            .long("node")
+                            .action(ArgAction::Append)
+                            .required(true)
+                            .help("Adding a node."),
+                    )
+                    .arg(
+                        Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output format.")
+                            .value_parser(output_formats.clone())
+                            .default_value(output_formats.first().unwrap()),
+                    ),
+            )
+            .subcommand(
+                clap::Command::new("explain")
+                    .about("Explains why a node is part of the plan for the given nodes.")
+                    .arg(
+                        Arg::new("workflow")
+                            .long("workflow")
+                            .help("The workflow file to use.")
+                            .default_value("./neomake.yaml"),
+                    )
+                    .arg(
+                        Arg::new("node")
+                            .short('n')
+                            .long("node")
+                            .action(ArgAction::Append)
+                            .required(true)
+                            .help("Adding a node that is requested for the plan."),
+                    )
+                    .arg(Arg::new("explained").required(true).help("The node to explain.")),
+            )
+            .subcommand(
+                clap::Command::new("graph")
+                    .about("Renders the dependency graph of the given nodes.")
+                    .visible_aliases(["g"])
+                    .arg(
+                        Arg::new("workflow")
+                            .long("workflow")
+                            .help("The workflow file to use.")
+                            .default_value("./neomake.yaml"),
+                    )
+                    .arg(
+                        Arg::new("node")
+                            .short('n')
+                            .long("node")
+                            .action(ArgAction::Append)
+                            .conflicts_with("regex")
+                            .required_unless_present("regex")
+                            .help("Adding a node."),
+                    )
+                    .arg(
+                        Arg::new("regex")
+                            .short('r')
+                            .long("regex")
+                            .conflicts_with("node")
+                            .required_unless_present("node")
+                            .help("Adding a node to the graph."),
+                    )
+                    .arg(
+                        Arg::new("output")
+                            .short('o')
+                            .long("output")
+                            .help("The output format.")
+                            .value_parser(["dot", "mermaid"])
+                            .default_value("dot"),
+                    )
+                    .arg(
+                        Arg::new("details")
+                            .long("details")
+                            .help("Adds the number of matrix entries and the description to the node labels.")
+                            .num_args(0),
+                    ),
+            )
             .subcommand(
                 clap::Command::new("list")
                     .about("Lists all available nodes.")
-                    .visible_aliases(&["ls", "l"])
+                    .visible_aliases(["ls", "l"])
                     .arg(
                         clap::Arg::new("workflow")
                             .long("workflow")
@@ -457,7 +730,7 @@ impl ClapArgumentLoader {
             .subcommand(
                 clap::Command::new("multiplex")
                     .about("Multiplex commands")
-                    .visible_aliases(&["m", "mp"])
+                    .visible_aliases(["m", "mp"])
                     .args([
                         clap::Arg::new("program")
                             .long("program")
@@ -512,7 +785,16 @@ impl ClapArgumentLoader {
     }
 
     pub(crate) fn load() -> Result<CallArgs> {
-        let command = Self::root_command().get_matches();
+        Self::load_from(std::env::args_os())
+    }
+
+    /// Parses the given command line, starting with the name of the binary.
+    pub(crate) fn load_from<I, T>(args: I) -> Result<CallArgs>
+    where
+        I: IntoIterator<Item=T>,
+        T: Into<std::ffi::OsString>+Clone,
+    {
+        let command = Self::root_command().get_matches_from(args);
 
         let privileges = if command.get_flag("experimental") {
             Privilege::Experimental
@@ -527,6 +809,38 @@ impl ClapArgumentLoader {
             }
         }
 
+        fn parse_args(x: &clap::ArgMatches) -> HashMap<String, String> {
+            let mut args_map: HashMap<String, String> = HashMap::new();
+            if let Some(args) = x.get_many::<String>("arg") {
+                for v_arg in args {
+                    let spl: Vec<&str> = v_arg.splitn(2, "=").collect();
+                    args_map.insert(spl[0].to_owned(), spl[1].to_owned());
+                }
+            }
+            args_map
+        }
+
+        fn parse_execution(x: &clap::ArgMatches) -> Result<ExecutionArgs> {
+            Ok(ExecutionArgs {
+                workers: str::parse::<usize>(x.get_one::<String>("workers").unwrap())?,
+                no_stdout: x.get_flag("no-stdout"),
+                no_stderr: x.get_flag("no-stderr"),
+                keep_going: x.get_flag("keep-going"),
+                events: x.get_one::<String>("events").cloned(),
+                junit: x.get_one::<String>("junit").cloned(),
+                resume: x.get_flag("resume"),
+                dry_run: x.get_flag("dry-run"),
+                state_dir: x.get_one::<String>("state-dir").unwrap().clone(),
+                output: match x.get_one::<String>("output").unwrap().as_str() {
+                    | "inherit" => OutputStyle::Inherit,
+                    | "prefix" => OutputStyle::Prefix { color: false },
+                    | "prefix+color" => OutputStyle::Prefix { color: true },
+                    | "grouped" => OutputStyle::Grouped,
+                    | _ => return Err(anyhow::anyhow!("argument \"output\": unknown output")),
+                },
+            })
+        }
+
         let cmd = if let Some(subc) = command.subcommand_matches("man") {
             Command::Manual {
                 path: subc.get_one::<String>("out").unwrap().into(),
@@ -540,6 +854,7 @@ impl ClapArgumentLoader {
             Command::Autocomplete {
                 path: subc.get_one::<String>("out").unwrap().into(),
                 shell: clap_complete::Shell::from_str(subc.get_one::<String>("shell").unwrap().as_str()).unwrap(),
+                workflow: subc.get_one::<String>("workflow").cloned(),
             }
         } else if let Some(x) = command.subcommand_matches("workflow") {
             if let Some(x) = x.subcommand_matches("init") {
@@ -555,43 +870,61 @@ impl ClapArgumentLoader {
                         | s => WorkflowInitOutput::File(s.to_owned()),
                     },
                 })
-            } else if let Some(_) = x.subcommand_matches("schema") {
+            } else if x.subcommand_matches("schema").is_some() {
                 Command::Workflow(WorkflowCommand::Schema)
+            } else if let Some(x) = x.subcommand_matches("lint") {
+                Command::Workflow(WorkflowCommand::Lint {
+                    workflow: x.get_one::<String>("workflow").unwrap().clone(),
+                })
             } else {
                 return Err(anyhow::anyhow!("unknown command"));
             }
-        } else if let Some(x) = command.subcommand_matches("execute") {
-            let mut args_map: HashMap<String, String> = HashMap::new();
-            if let Some(args) = x.get_many::<String>("arg") {
-                for v_arg in args {
-                    let spl: Vec<&str> = v_arg.splitn(2, "=").collect();
-                    args_map.insert(spl[0].to_owned(), spl[1].to_owned());
+        } else if let Some(x) = command.subcommand_matches("events") {
+            if x.subcommand_matches("schema").is_some() {
+                Command::Events(EventsCommand::Schema)
+            } else {
+                return Err(anyhow::anyhow!("unknown command"));
+            }
+        } else if let Some(x) = command.subcommand_matches("cache") {
+            let cache_command = if x.subcommand_matches("ls").is_some() {
+                CacheCommand::List
+            } else if let Some(x) = x.subcommand_matches("prune") {
+                CacheCommand::Prune {
+                    older_than: humantime_serde::re::humantime::parse_duration(
+                        x.get_one::<String>("older-than").unwrap(),
+                    )
+                    .map_err(|e| anyhow::anyhow!("argument \"older-than\": {}", e))?,
                 }
+            } else if x.subcommand_matches("clear").is_some() {
+                CacheCommand::Clear
+            } else {
+                return Err(anyhow::anyhow!("unknown command"));
+            };
+            Command::Cache {
+                state_dir: x.get_one::<String>("state-dir").unwrap().clone(),
+                command: cache_command,
             }
-
+        } else if let Some(x) = command.subcommand_matches("execute") {
             let format = Format::from_arg(x.get_one::<String>("format").unwrap().as_str())?;
             let mut plan = String::new();
             std::io::stdin().read_to_string(&mut plan)?;
 
             Command::Execute {
                 plan: format.deserialize::<ExecutionPlan>(&plan)?,
-                workers: str::parse::<usize>(x.get_one::<String>("workers").unwrap()).unwrap(),
-                no_stdout: x.get_flag("no-stdout"),
-                no_stderr: x.get_flag("no-stderr"),
+                execution: parse_execution(x)?,
             }
-        } else if let Some(x) = command.subcommand_matches("plan") {
-            let mut args_map: HashMap<String, String> = HashMap::new();
-            if let Some(args) = x.get_many::<String>("arg") {
-                for v_arg in args {
-                    let spl: Vec<&str> = v_arg.splitn(2, "=").collect();
-                    args_map.insert(spl[0].to_owned(), spl[1].to_owned());
-                }
+        } else if let Some(x) = command.subcommand_matches("run") {
+            Command::Run {
+                workflow: x.get_one::<String>("workflow").unwrap().clone(),
+                nodes: parse_nodes(x),
+                args: parse_args(x),
+                execution: parse_execution(x)?,
             }
-
+        } else if let Some(x) = command.subcommand_matches("plan") {
             Command::Plan {
                 workflow: x.get_one::<String>("workflow").unwrap().clone(),
                 nodes: parse_nodes(x),
-                args: args_map,
+                args: parse_args(x),
                 format: Format::from_arg(x.get_one::<String>("output").unwrap().as_str())?,
             }
         } else if let Some(x) = command.subcommand_matches("list") {
@@ -608,6 +941,29 @@ impl ClapArgumentLoader {
                 nodes: parse_nodes(x),
                 format: Format::from_arg(x.get_one::<String>("output").unwrap().as_str())?,
             }
+        } else if let Some(x) = command.subcommand_matches("dependents") {
+            Command::Dependents {
+                workflow: x.get_one::<String>("workflow").unwrap().clone(),
+                nodes: HashSet::<String>::from_iter(x.get_many::<String>("node").unwrap().cloned()),
+                format: Format::from_arg(x.get_one::<String>("output").unwrap().as_str())?,
+            }
+        } else if let Some(x) = command.subcommand_matches("explain") {
+            Command::Explain {
+                workflow: x.get_one::<String>("workflow").unwrap().clone(),
+                targets: x.get_many::<String>("node").unwrap().cloned().collect(),
+                node: x.get_one::<String>("explained").unwrap().clone(),
+            }
+        } else if let Some(x) = command.subcommand_matches("graph") {
+            Command::Graph {
+                workflow: x.get_one::<String>("workflow").unwrap().clone(),
+                nodes: parse_nodes(x),
+                format: match x.get_one::<String>("output").unwrap().as_str() {
+                    | "dot" => GraphFormat::Dot,
+                    | "mermaid" => GraphFormat::Mermaid,
+                    | _ => return Err(anyhow::anyhow!("argument \"output\": unknown format")),
+                },
+                details: x.get_flag("details"),
+            }
         } else if let Some(x) = command.subcommand_matches("watch") {
             let commands = x
                 .get_many::<String>("command")
@@ -619,7 +975,6 @@ impl ClapArgumentLoader {
                 .get_one::<String>("program")
                 .unwrap()
                 .split_whitespace()
-                .into_iter()
                 .map(|v| v.to_owned())
                 .collect::<Vec<_>>();
             Command::Watch {
@@ -644,7 +999,6 @@ impl ClapArgumentLoader {
                 .get_one::<String>("program")
                 .unwrap()
                 .split_whitespace()
-                .into_iter()
                 .map(|v| v.to_owned())
                 .collect::<Vec<_>>();
             Command::Multiplex {
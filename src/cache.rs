@@ -0,0 +1,353 @@
+use {
+    crate::{
+        plan,
+        state,
+    },
+    anyhow::Result,
+    itertools::Itertools,
+    sha2::{
+        Digest,
+        Sha256,
+    },
+    std::{
+        collections::HashSet,
+        os::unix::fs::PermissionsExt,
+        path::{
+            Component,
+            Path,
+            PathBuf,
+        },
+        time::Duration,
+    },
+};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+/// The outputs of one successful node execution.
+pub(crate) struct CacheEntry {
+    pub node: String,
+    pub fingerprint: String,
+    pub created: chrono::DateTime<chrono::Utc>,
+    pub files: Vec<CachedFile>,
+    /// Symbolic links are stored as such instead of the files they point to.
+    #[serde(default)]
+    pub links: Vec<CachedLink>,
+}
+
+impl CacheEntry {
+    /// Checks that the fingerprint and all blobs are SHA-256 digests and that
+    /// all paths stay within the workdir. Anything else can only stem from a
+    /// corrupt or tampered entry.
+    fn validate(&self) -> Result<()> {
+        if !is_digest(&self.fingerprint) {
+            Err(anyhow::anyhow!("invalid fingerprint: {}", self.fingerprint))?
+        }
+        if let Some(file) = self.files.iter().find(|f| !is_digest(&f.blob)) {
+            Err(anyhow::anyhow!("invalid blob for {}: {}", file.path, file.blob))?
+        }
+        let mut paths = self
+            .files
+            .iter()
+            .map(|f| &f.path)
+            .chain(self.links.iter().map(|l| &l.path));
+        if let Some(path) = paths.find(|v| !is_contained(Path::new(v))) {
+            Err(anyhow::anyhow!("invalid path: {}", path))?
+        }
+        Ok(())
+    }
+}
+
+/// A cache entry that can not be used.
+#[derive(Debug)]
+pub(crate) struct CorruptEntry {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Whether the path is a non-empty relative path without `.` and `..`
+/// components, i.e. it can not point outside of the directory it is joined to.
+fn is_contained(path: &Path) -> bool {
+    !path.as_os_str().is_empty() && path.components().all(|v| matches!(v, Component::Normal(_)))
+}
+
+/// The path relative to `base` if it is contained in `base`. Both paths are
+/// compared lexically.
+fn relative(base: &Path, path: &Path) -> Option<PathBuf> {
+    let normalize = |v: &Path| v.components().filter(|v| *v != Component::CurDir).collect::<PathBuf>();
+    let path = normalize(path).strip_prefix(normalize(base)).ok()?.to_path_buf();
+    is_contained(&path).then_some(path)
+}
+
+/// Whether the value is a hex encoded SHA-256 digest.
+fn is_digest(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|v| v.is_ascii_hexdigit())
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) struct CachedFile {
+    /// Path of the file relative to the workdir of the node.
+    pub path: String,
+    /// SHA-256 of the content which also names the blob.
+    pub blob: String,
+    pub size: u64,
+    pub mode: u32,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) struct CachedLink {
+    /// Path of the link relative to the workdir of the node.
+    pub path: String,
+    /// The target as stored in the link, it is not resolved.
+    pub target: String,
+}
+
+/// Age after which temporary blobs are considered leftovers of a crashed
+/// process rather than blobs that are still being written.
+const TMP_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Content addressed store for the outputs of nodes in `cache` in the state
+/// directory.
+/// Entries are keyed by the fingerprint of the node, blobs by their content.
+pub(crate) struct Cache {
+    root: PathBuf,
+}
+
+impl Cache {
+    pub fn new(state_dir: &Path) -> Self {
+        Self {
+            root: state_dir.join("cache"),
+        }
+    }
+
+    fn entry_path(&self, fingerprint: &str) -> PathBuf {
+        self.root.join("entries").join(format!("{}.json", fingerprint))
+    }
+
+    /// The path of a blob, sharded by the first two characters of the digest.
+    /// Only called with validated digests.
+    fn blob_path(&self, blob: &str) -> PathBuf {
+        self.root.join("blobs").join(&blob[..2]).join(blob)
+    }
+
+    /// Writes to a temporary file first that is moved into place afterwards to
+    /// never leave partial files behind, also not if multiple processes write
+    /// the same file at once.
+    pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Reads and validates the entry stored in the given file.
+    fn read_entry(path: &Path) -> Result<CacheEntry> {
+        let entry = serde_json::from_str::<CacheEntry>(&std::fs::read_to_string(path)?)?;
+        entry.validate()?;
+        Ok(entry)
+    }
+
+    /// Stores all outputs of the node under the given fingerprint. Directories
+    /// matched by an output pattern are stored recursively, symbolic links
+    /// within them are not followed. Outputs outside of the workdir of the node
+    /// can not be stored.
+    pub fn store(&self, name: &str, fingerprint: &str, node: &plan::Node) -> Result<()> {
+        let base = Path::new(node.workdir.as_deref().unwrap_or("."));
+        let paths = state::resolve(node.workdir.as_deref(), &node.outputs)?
+            .into_iter()
+            .flatten()
+            .flat_map(|v| {
+                walkdir::WalkDir::new(v)
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file() || e.path_is_symlink())
+                    .map(|e| e.into_path())
+            })
+            .sorted()
+            .dedup();
+
+        let mut files = Vec::<CachedFile>::new();
+        let mut links = Vec::<CachedLink>::new();
+        for path in paths {
+            let relative = relative(base, &path)
+                .ok_or(anyhow::anyhow!("output {} is outside of the workdir", path.display()))?
+                .to_string_lossy()
+                .into_owned();
+            if path.symlink_metadata()?.file_type().is_symlink() {
+                links.push(CachedLink {
+                    path: relative,
+                    target: std::fs::read_link(&path)?.to_string_lossy().into_owned(),
+                });
+                continue;
+            }
+            let data = std::fs::read(&path)?;
+            let blob = format!("{:x}", Sha256::digest(&data));
+            let blob_path = self.blob_path(&blob);
+            if !blob_path.exists() {
+                Self::write_atomic(&blob_path, &data)?;
+            }
+            files.push(CachedFile {
+                path: relative,
+                blob,
+                size: data.len() as u64,
+                mode: std::fs::metadata(&path)?.permissions().mode(),
+            });
+        }
+
+        let entry = CacheEntry {
+            node: name.to_owned(),
+            fingerprint: fingerprint.to_owned(),
+            created: chrono::Utc::now(),
+            files,
+            links,
+        };
+        Self::write_atomic(
+            &self.entry_path(fingerprint),
+            serde_json::to_string_pretty(&entry)?.as_bytes(),
+        )
+    }
+
+    /// Restores the outputs stored under the given fingerprint into the workdir
+    /// of the node. All current outputs of the node are removed first so that
+    /// nothing of other executions is left behind. Returns `false` if there is
+    /// no (complete) entry for it.
+    pub fn restore(&self, fingerprint: &str, node: &plan::Node) -> Result<bool> {
+        let base = Path::new(node.workdir.as_deref().unwrap_or("."));
+        let entry_path = self.entry_path(fingerprint);
+        if !entry_path.exists() {
+            return Ok(false);
+        }
+        let entry = Self::read_entry(&entry_path)
+            .map_err(|e| anyhow::anyhow!("corrupt cache entry {}: {}", entry_path.display(), e))?;
+        if !entry.files.iter().all(|f| self.blob_path(&f.blob).exists()) {
+            return Ok(false);
+        }
+        for path in state::resolve(node.workdir.as_deref(), &node.outputs)?
+            .into_iter()
+            .flatten()
+        {
+            match path.symlink_metadata() {
+                | Ok(v) if v.is_dir() => std::fs::remove_dir_all(&path)?,
+                | Ok(_) => std::fs::remove_file(&path)?,
+                | Err(_) => {},
+            }
+        }
+        for file in &entry.files {
+            let path = base.join(&file.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            self.restore_file(file, &path)?;
+        }
+        for link in &entry.links {
+            let path = base.join(&link.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            if path.symlink_metadata().is_ok() {
+                std::fs::remove_file(&path)?;
+            }
+            std::os::unix::fs::symlink(&link.target, &path)?;
+        }
+        Ok(true)
+    }
+
+    /// Copies the blob of the file to a temporary file that is moved into place
+    /// afterwards. Whatever is at `path` is replaced, links are never written
+    /// through.
+    fn restore_file(&self, file: &CachedFile, path: &Path) -> Result<()> {
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        if tmp.symlink_metadata().is_ok() {
+            std::fs::remove_file(&tmp)?;
+        }
+        std::fs::copy(self.blob_path(&file.blob), &tmp)?;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(file.mode))?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// All entries, oldest first, and all entries that can not be read.
+    pub fn entries(&self) -> Result<(Vec<CacheEntry>, Vec<CorruptEntry>)> {
+        let dir = self.root.join("entries");
+        if !dir.exists() {
+            return Ok((vec![], vec![]));
+        }
+        let mut entries = Vec::<CacheEntry>::new();
+        let mut corrupt = Vec::<CorruptEntry>::new();
+        for file in std::fs::read_dir(dir)? {
+            let path = file?.path();
+            if path.extension().is_some_and(|v| v == "json") {
+                match Self::read_entry(&path) {
+                    | Ok(v) => entries.push(v),
+                    | Err(e) => {
+                        corrupt.push(CorruptEntry {
+                            path,
+                            reason: e.to_string(),
+                        })
+                    },
+                }
+            }
+        }
+        entries.sort_by_key(|v| v.created);
+        corrupt.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok((entries, corrupt))
+    }
+
+    /// Removes all entries older than the given age and all corrupt entries as
+    /// well as all blobs that are not referenced anymore. Temporary blobs are
+    /// only removed after `TMP_GRACE_PERIOD` as another process might still
+    /// write them. Returns the number of removed entries and blobs.
+    pub fn prune(&self, older_than: Duration) -> Result<(usize, usize)> {
+        let threshold = chrono::Utc::now() - chrono::Duration::from_std(older_than)?;
+        let mut removed_entries = 0_usize;
+        let mut referenced = HashSet::<String>::new();
+        let (entries, corrupt) = self.entries()?;
+        for entry in corrupt {
+            std::fs::remove_file(entry.path)?;
+            removed_entries += 1;
+        }
+        for entry in entries {
+            if entry.created < threshold {
+                std::fs::remove_file(self.entry_path(&entry.fingerprint))?;
+                removed_entries += 1;
+            } else {
+                referenced.extend(entry.files.into_iter().map(|f| f.blob));
+            }
+        }
+
+        let mut removed_blobs = 0_usize;
+        let blobs = self.root.join("blobs");
+        if blobs.exists() {
+            // directories come after their content so that emptied ones can be removed
+            for file in walkdir::WalkDir::new(blobs).min_depth(1).contents_first(true) {
+                let file = file?;
+                if file.file_type().is_dir() {
+                    // fails as intended if the directory is not empty
+                    let _ = std::fs::remove_dir(file.path());
+                    continue;
+                }
+                let name = file.file_name().to_string_lossy();
+                if name.ends_with(".tmp")
+                    && file.metadata()?.modified()?.elapsed().unwrap_or_default() < TMP_GRACE_PERIOD
+                {
+                    continue;
+                }
+                if !referenced.contains(name.as_ref()) {
+                    std::fs::remove_file(file.path())?;
+                    removed_blobs += 1;
+                }
+            }
+        }
+        Ok((removed_entries, removed_blobs))
+    }
+
+    /// Removes the entire cache.
+    pub fn clear(&self) -> Result<()> {
+        if self.root.exists() {
+            std::fs::remove_dir_all(&self.root)?;
+        }
+        Ok(())
+    }
+}
@@ -1,13 +1,18 @@
 use {
     crate::{
-        args::CommandListFormat,
+        args::{
+            CommandListFormat,
+            GraphFormat,
+        },
         plan,
         workflow::{
+            Node,
             NodeSelector,
             Workflow,
         },
     },
     anyhow::Result,
+    itertools::Itertools,
     std::{
         collections::{
             HashMap,
@@ -22,6 +27,32 @@ pub(crate) struct Compiler {
     pub workflow: Workflow,
 }
 
+/// A prerequisite on the way from a target to an explained node.
+#[derive(Debug)]
+pub(crate) struct Step<'a> {
+    /// Depth below the target, starting at 0 for its direct prerequisites.
+    pub depth: usize,
+    pub node: String,
+    /// Selector of the dependent that matched the prerequisite.
+    pub selector: &'a NodeSelector,
+    /// The prerequisite was already expanded before.
+    pub repeated: bool,
+}
+
+impl std::fmt::Display for Step<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-> {} (", "  ".repeat(self.depth + 1), self.node)?;
+        match self.selector {
+            | NodeSelector::Name(_) => write!(f, "pre name")?,
+            | NodeSelector::Regex(v) => write!(f, "pre regex \"{}\"", v)?,
+        }
+        if self.repeated {
+            write!(f, ", see above")?;
+        }
+        write!(f, ")")
+    }
+}
+
 impl Compiler {
     pub fn new(wf: Workflow) -> Self {
         Self { workflow: wf }
@@ -30,8 +61,13 @@ impl Compiler {
     pub fn plan(&self, nodes: &HashSet<String>, args: &HashMap<String, String>) -> Result<plan::ExecutionPlan> {
         let mut hb = handlebars::Handlebars::new();
         hb.set_strict_mode(true);
-        let arg_vals = self.compile_exec_args(args)?;
-        let stages = self.determine_order(nodes)?;
+        let graph = self.determine_graph(nodes)?;
+        let used = graph
+            .keys()
+            .flat_map(|v| Self::placeholders(&self.workflow.nodes[v]))
+            .collect::<HashSet<_>>();
+        let arg_vals = self.compile_exec_args(args, &used)?;
+        let stages = self.determine_stages(&graph)?;
 
         let mut plan = plan::ExecutionPlan {
             version: env!("CARGO_PKG_VERSION").to_owned(),
@@ -48,12 +84,15 @@ impl Compiler {
             for node in stage {
                 let node_def = &self.workflow.nodes[&node];
                 let mut rendered_node = plan::Node {
+                    pre: Some(graph[&node].clone()),
                     parallel: match &node_def.matrix {
                         | Some(v) => v.parallel,
                         | None => false,
                     },
                     invocations: vec![],
                     tasks: vec![],
+                    inputs: node_def.inputs.clone().unwrap_or_default(),
+                    outputs: node_def.outputs.clone().unwrap_or_default(),
                     env: match &node_def.env {
                         | Some(v) => v.compile()?,
                         | None => HashMap::<_, _>::new(),
@@ -63,18 +102,48 @@ impl Compiler {
                         | None => None,
                     },
                     workdir: node_def.workdir.clone(),
+                    timeout: node_def.timeout,
+                    retry: node_def.retry.clone(),
+                    allow_failure: node_def.allow_failure.clone(),
                 };
 
                 // default to one matrix entry
                 let invocation_default = vec![crate::plan::Invocation { ..Default::default() }];
+                rendered_node.invocations = match &node_def.matrix {
+                    | Some(m) => m.compile()?,
+                    | None => invocation_default,
+                };
 
-                for task in &node_def.tasks {
-                    let rendered_cmd = hb.render_template(&task.script, &arg_vals)?;
+                // the values of every matrix entry are available under "matrix"
+                let invocation_vals = rendered_node
+                    .invocations
+                    .iter()
+                    .map(|v| {
+                        let mut matrix = serde_json::Map::new();
+                        for (key, value) in &v.values {
+                            matrix.insert(key.clone(), serde_json::Value::String(value.clone()));
+                        }
+                        matrix.insert("cell".to_owned(), serde_json::json!(v.cell));
+                        let mut vals = arg_vals.clone();
+                        vals.as_object_mut()
+                            .unwrap()
+                            .insert("matrix".to_owned(), serde_json::Value::Object(matrix));
+                        vals
+                    })
+                    .collect_vec();
+
+                for (idx, task) in node_def.tasks.iter().enumerate() {
+                    let template = format!("{}.tasks[{}]", node, idx);
+                    hb.register_template_string(&template, &task.script)?;
+                    let mut rendered_cmds = Vec::<String>::new();
+                    for vals in &invocation_vals {
+                        rendered_cmds.push(hb.render(&template, vals)?);
+                    }
 
                     rendered_node.tasks.push(plan::Task {
-                        cmd: rendered_cmd,
+                        cmds: rendered_cmds,
                         shell: match task.shell.clone() {
-                            | Some(v) => Some(v.into()),
+                            | Some(v) => Some(v),
                             | None => None,
                         },
                         env: match task.env.clone() {
@@ -82,14 +151,12 @@ impl Compiler {
                             | None => HashMap::<_, _>::new(),
                         },
                         workdir: task.workdir.clone(),
+                        timeout: task.timeout,
+                        retry: task.retry.clone(),
+                        allow_failure: task.allow_failure.clone(),
                     });
                 }
 
-                rendered_node.invocations = match &node_def.matrix {
-                    | Some(m) => m.compile()?,
-                    | None => invocation_default,
-                };
-
                 plan.nodes.insert(node.clone(), rendered_node);
                 rendered_stage.nodes.push(node);
             }
@@ -111,6 +178,21 @@ impl Compiler {
             description: Option<String>,
             #[serde(skip_serializing_if = "Option::is_none")]
             pre: Option<Vec<String>>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            args: Vec<OutputArg>,
+        }
+        #[derive(Debug, serde::Serialize)]
+        struct OutputArg {
+            name: String,
+            #[serde(rename = "type")]
+            kind: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            description: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            values: Option<Vec<String>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            default: Option<String>,
+            required: bool,
         }
 
         let mut info = Output {
@@ -140,6 +222,25 @@ impl Compiler {
                         },
                         | None => None,
                     },
+                    args: {
+                        let used = Self::placeholders(c.1);
+                        self.workflow
+                            .args
+                            .iter()
+                            .flatten()
+                            .filter(|v| used.contains(&v.name))
+                            .map(|v| {
+                                OutputArg {
+                                    name: v.name.clone(),
+                                    kind: v.kind.to_string(),
+                                    description: v.description.clone(),
+                                    values: v.values.clone(),
+                                    default: v.default_value(),
+                                    required: v.required,
+                                }
+                            })
+                            .collect()
+                    },
                 }
             })),
         };
@@ -155,7 +256,23 @@ impl Compiler {
                     if let Some(pre) = &n.pre {
                         println!("prerequisites: [\"{}\"]", pre.join("\", \""))
                     }
-                    println!("");
+                    for arg in &n.args {
+                        let mut details = vec![arg.kind.clone()];
+                        if let Some(values) = &arg.values {
+                            details.push(format!("one of: {}", values.join(", ")));
+                        }
+                        if let Some(default) = &arg.default {
+                            details.push(format!("default: {}", default));
+                        }
+                        if arg.required {
+                            details.push("required".to_owned());
+                        }
+                        match &arg.description {
+                            | Some(desc) => println!("argument {} ({}): {}", arg.name, details.join(", "), desc),
+                            | None => println!("argument {} ({})", arg.name, details.join(", ")),
+                        }
+                    }
+                    println!();
                 }
             },
             | CommandListFormat::Standard(format) => {
@@ -167,7 +284,7 @@ impl Compiler {
     }
 
     pub async fn describe(&self, nodes: &HashSet<String>, format: &crate::args::Format) -> Result<()> {
-        let structure = self.determine_order(&nodes)?;
+        let structure = self.determine_order(nodes)?;
 
         #[derive(Debug, serde::Serialize)]
         struct Output {
@@ -176,8 +293,7 @@ impl Compiler {
 
         let mut info = Output { stages: Vec::new() };
         for s in structure {
-            info.stages
-                .push(s.iter().map(|s| s.to_owned()).into_iter().collect::<Vec<_>>());
+            info.stages.push(s.iter().map(|s| s.to_owned()).collect::<Vec<_>>());
         }
 
         println!("{}", format.serialize(&info)?);
@@ -185,11 +301,136 @@ impl Compiler {
         Ok(())
     }
 
-    fn compile_exec_args(&self, args: &HashMap<String, String>) -> Result<serde_json::Value> {
+    pub async fn dependents(&self, nodes: &HashSet<String>, format: &crate::args::Format) -> Result<()> {
+        let (direct, all) = self.determine_dependents(nodes)?;
+
+        #[derive(Debug, serde::Serialize)]
+        struct Output {
+            /// Nodes that list any of the given nodes as prerequisite.
+            direct: Vec<String>,
+            /// All nodes that depend on any of the given nodes on any level.
+            all: Vec<String>,
+        }
+
+        println!("{}", format.serialize(&Output { direct, all })?);
+
+        Ok(())
+    }
+
+    pub async fn explain(&self, targets: &[String], node: &str) -> Result<()> {
+        let mut found = false;
+        for target in targets {
+            let Some(steps) = self.determine_chains(target, node)? else {
+                continue;
+            };
+            if found {
+                println!();
+            }
+            found = true;
+            if steps.is_empty() {
+                println!("{} (requested)", target);
+                continue;
+            }
+            println!("{}", target);
+            for step in steps {
+                println!("{}", step);
+            }
+        }
+        if !found {
+            return Err(anyhow::anyhow!(
+                "node {} is not part of a plan for {}",
+                node,
+                targets.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn graph(&self, nodes: &HashSet<String>, format: &GraphFormat, details: bool) -> Result<()> {
+        print!("{}", self.render_graph(nodes, format, details)?);
+        Ok(())
+    }
+
+    /// Renders the dependency graph of the given nodes with one edge from every
+    /// prerequisite to its dependent.
+    pub(crate) fn render_graph(&self, nodes: &HashSet<String>, format: &GraphFormat, details: bool) -> Result<String> {
+        let graph = self.determine_graph(nodes)?;
+        let names = graph.keys().sorted().collect_vec();
+
+        let label = |name: &str| -> Result<Vec<String>> {
+            let mut lines = vec![name.to_owned()];
+            if details {
+                let node = &self.workflow.nodes[name];
+                let cells = match &node.matrix {
+                    | Some(m) => m.compile()?.len(),
+                    | None => 1,
+                };
+                lines.push(format!("matrix entries: {}", cells));
+                if let Some(desc) = &node.description {
+                    lines.push(desc.trim().to_owned());
+                }
+            }
+            Ok(lines)
+        };
+        let labels = names
+            .iter()
+            .map(|v| Ok((*v, label(v)?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let mut out = String::new();
+        match format {
+            | GraphFormat::Dot => {
+                let escape = |v: &str| v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+                out.push_str("digraph neomake {\n  rankdir=LR;\n");
+                for name in &names {
+                    let label = labels[*name].iter().map(|v| escape(v)).join("\\n");
+                    out.push_str(&format!("  \"{}\" [label=\"{}\"];\n", escape(name), label));
+                }
+                for name in &names {
+                    for pre in &graph[*name] {
+                        out.push_str(&format!("  \"{}\" -> \"{}\";\n", escape(pre), escape(name)));
+                    }
+                }
+                out.push_str("}\n");
+            },
+            | GraphFormat::Mermaid => {
+                // node names may contain characters that are not allowed in mermaid IDs
+                let ids = names
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| (*v, format!("n{}", i)))
+                    .collect::<HashMap<_, _>>();
+                let escape = |v: &str| {
+                    v.replace('&', "#amp;")
+                        .replace('"', "#quot;")
+                        .replace('<', "#lt;")
+                        .replace('>', "#gt;")
+                        .replace('\n', "<br/>")
+                };
+                out.push_str("graph LR\n");
+                for name in &names {
+                    let label = labels[*name].iter().map(|v| escape(v)).join("<br/>");
+                    out.push_str(&format!("  {}[\"{}\"]\n", ids[*name], label));
+                }
+                for name in &names {
+                    for pre in &graph[*name] {
+                        out.push_str(&format!("  {} --> {}\n", ids[pre], ids[*name]));
+                    }
+                }
+            },
+        }
+        Ok(out)
+    }
+
+    /// Converts the passed arguments into the values for the handlebars
+    /// placeholders. Declared arguments are validated, converted to their type
+    /// and defaulted. Missing required arguments only fail if they are `used`
+    /// by the planned nodes.
+    fn compile_exec_args(&self, args: &HashMap<String, String>, used: &HashSet<String>) -> Result<serde_json::Value> {
         fn recursive_add(
             namespace: &mut std::collections::VecDeque<String>,
             parent: &mut serde_json::Value,
-            value: &str,
+            value: serde_json::Value,
         ) {
             let current_namespace = namespace.pop_front().unwrap();
             match namespace.len() {
@@ -198,7 +439,7 @@ impl Compiler {
                         .as_object_mut()
                         .unwrap()
                         .entry(&current_namespace)
-                        .or_insert(serde_json::Value::String(value.to_owned()));
+                        .or_insert(value);
                 },
                 | _ => {
                     let p = parent
@@ -210,16 +451,92 @@ impl Compiler {
                 },
             }
         }
-        let mut values_json = serde_json::Value::Object(serde_json::Map::new());
+        let declared = self.workflow.args.iter().flatten().collect_vec();
+        let mut values = Vec::<(String, serde_json::Value)>::new();
         for arg in args {
-            let namespaces_vec: Vec<String> = arg.0.split('.').map(|s| s.to_string()).collect();
+            let value = match declared.iter().find(|v| &v.name == arg.0) {
+                | Some(v) => v.coerce(arg.1)?,
+                | None => serde_json::Value::String(arg.1.clone()),
+            };
+            values.push((arg.0.clone(), value));
+        }
+        for arg in &declared {
+            if args.contains_key(&arg.name) {
+                continue;
+            }
+            match arg.default_value() {
+                | Some(v) => values.push((arg.name.clone(), arg.coerce(&v)?)),
+                | None if arg.required && used.contains(&arg.name) => {
+                    Err(anyhow::anyhow!(
+                        "missing required argument {} (pass it with -a {}=<value>)",
+                        arg.name,
+                        arg.name
+                    ))?
+                },
+                | None => {},
+            }
+        }
+
+        let mut values_json = serde_json::Value::Object(serde_json::Map::new());
+        for (name, value) in values {
+            let namespaces_vec: Vec<String> = name.split('.').map(|s| s.to_string()).collect();
             let mut namespaces = VecDeque::from(namespaces_vec);
-            recursive_add(&mut namespaces, &mut values_json, arg.1);
+            recursive_add(&mut namespaces, &mut values_json, value);
         }
         Ok(values_json)
     }
 
-    fn determine_order(&self, exec: &HashSet<String>) -> Result<Vec<HashSet<String>>> {
+    /// All placeholder paths (e.g. `args.env`) referenced by the scripts of the
+    /// node. Scripts that are no valid templates are ignored here.
+    pub(crate) fn placeholders(node: &Node) -> HashSet<String> {
+        use handlebars::template::{
+            HelperTemplate,
+            Parameter,
+            TemplateElement,
+        };
+        fn add_elements(elements: &[TemplateElement], paths: &mut HashSet<String>) {
+            for element in elements {
+                match element {
+                    | TemplateElement::Expression(v)
+                    | TemplateElement::HtmlExpression(v)
+                    | TemplateElement::HelperBlock(v) => add_helper(v, paths),
+                    | _ => {},
+                }
+            }
+        }
+        fn add_helper(helper: &HelperTemplate, paths: &mut HashSet<String>) {
+            for param in std::iter::once(&helper.name)
+                .chain(&helper.params)
+                .chain(helper.hash.values())
+            {
+                match param {
+                    | Parameter::Name(v) => {
+                        paths.insert(v.clone());
+                    },
+                    | Parameter::Path(handlebars::Path::Relative((_, v))) => {
+                        paths.insert(v.clone());
+                    },
+                    | Parameter::Subexpression(v) => add_elements(std::slice::from_ref(&v.element), paths),
+                    | _ => {},
+                }
+            }
+            for template in helper.template.iter().chain(&helper.inverse) {
+                add_elements(&template.elements, paths);
+            }
+        }
+
+        let mut paths = HashSet::<String>::new();
+        for task in &node.tasks {
+            if let Ok(template) = handlebars::Template::compile(&task.script) {
+                add_elements(&template.elements, &mut paths);
+            }
+        }
+        paths
+    }
+
+    /// Resolves the requested nodes and all of their (transitive) prerequisites
+    /// into a map of node name to the names of its direct prerequisites.
+    fn determine_graph(&self, exec: &HashSet<String>) -> Result<HashMap<String, Vec<String>>> {
         let mut map = HashMap::<String, Vec<String>>::new();
 
         let mut seen = HashSet::<String>::new();
@@ -237,33 +554,189 @@ impl Compiler {
                 return Err(anyhow::anyhow!("node not found: {}", next));
             }
 
-            if let Some(pre) = &c.unwrap().pre {
-                let mut pre_nodes = Vec::<String>::new();
-                for sel in pre {
-                    match &sel {
-                        | NodeSelector::Name(v) => {
-                            pre_nodes.push(v.clone());
-                        },
-                        | NodeSelector::Regex(v) => {
-                            let regex = fancy_regex::Regex::new(v).unwrap();
-                            for n in self.workflow.nodes.keys() {
-                                if regex.is_match(n).unwrap() {
-                                    pre_nodes.push(n.clone());
-                                }
-                            }
-                        },
+            let pre_nodes = self.resolve_pre(c.unwrap())?;
+            map.insert(next, pre_nodes.clone());
+            pending.extend(pre_nodes);
+        }
+
+        Ok(map)
+    }
+
+    /// Resolves the prerequisite selectors of a node into the sorted and
+    /// deduplicated names of its direct prerequisites.
+    fn resolve_pre(&self, node: &Node) -> Result<Vec<String>> {
+        Ok(self
+            .resolve_pre_edges(node)?
+            .into_iter()
+            .map(|v| v.0)
+            .sorted()
+            .dedup()
+            .collect())
+    }
+
+    /// Resolves the prerequisite selectors of a node into the names of its
+    /// direct prerequisites, each with the selector that matched it.
+    fn resolve_pre_edges<'a>(&self, node: &'a Node) -> Result<Vec<(String, &'a NodeSelector)>> {
+        let mut edges = Vec::<(String, &NodeSelector)>::new();
+        for sel in node.pre.iter().flatten() {
+            match &sel {
+                | NodeSelector::Name(v) => {
+                    edges.push((v.clone(), sel));
+                },
+                | NodeSelector::Regex(v) => {
+                    let regex = fancy_regex::Regex::new(v)?;
+                    for n in self.workflow.nodes.keys().sorted() {
+                        if regex.is_match(n)? {
+                            edges.push((n.clone(), sel));
+                        }
+                    }
+                },
+            }
+        }
+        Ok(edges)
+    }
+
+    /// Determines how the target depends on the given node as a tree of
+    /// prerequisites, in depth-first order. Every step contains its depth below
+    /// the target, the prerequisite and the selector of its dependent that
+    /// matched it. Prerequisites that were already expanded are marked as
+    /// repeated and not expanded again which keeps the tree linear in the size
+    /// of the workflow. Returns `None` if the target does not depend on the
+    /// node.
+    pub(crate) fn determine_chains<'a>(&'a self, target: &str, node: &str) -> Result<Option<Vec<Step<'a>>>> {
+        struct Walk<'a, 'b> {
+            compiler: &'a Compiler,
+            node: &'b str,
+            edges: HashMap<String, Vec<(String, &'a NodeSelector)>>,
+            reaches: HashMap<String, bool>,
+            expanded: HashSet<String>,
+            steps: Vec<Step<'a>>,
+        }
+
+        impl<'a> Walk<'a, '_> {
+            fn edges(&mut self, current: &str) -> Result<Vec<(String, &'a NodeSelector)>> {
+                if let Some(v) = self.edges.get(current) {
+                    return Ok(v.clone());
+                }
+                let def = self
+                    .compiler
+                    .workflow
+                    .nodes
+                    .get(current)
+                    .ok_or(anyhow::anyhow!("node not found: {}", current))?;
+                let edges = self.compiler.resolve_pre_edges(def)?;
+                self.edges.insert(current.to_owned(), edges.clone());
+                Ok(edges)
+            }
+
+            /// Whether the node is reachable from the current one.
+            fn reaches(&mut self, current: &str) -> Result<bool> {
+                if current == self.node {
+                    return Ok(true);
+                }
+                if let Some(v) = self.reaches.get(current) {
+                    return Ok(*v);
+                }
+                // guards against recursions, they are reported when planning
+                self.reaches.insert(current.to_owned(), false);
+                let mut result = false;
+                for (pre, _) in self.edges(current)? {
+                    if self.reaches(&pre)? {
+                        result = true;
                     }
                 }
-                map.insert(next, pre_nodes.clone());
-                pending.extend(pre_nodes);
-            } else {
-                map.insert(next, Vec::<String>::new());
+                self.reaches.insert(current.to_owned(), result);
+                Ok(result)
+            }
+
+            fn expand(&mut self, current: &str, depth: usize) -> Result<()> {
+                for (pre, selector) in self.edges(current)? {
+                    if !self.reaches(&pre)? {
+                        continue;
+                    }
+                    let repeated = pre != self.node && !self.expanded.insert(pre.clone());
+                    self.steps.push(Step {
+                        depth,
+                        node: pre.clone(),
+                        selector,
+                        repeated,
+                    });
+                    if pre != self.node && !repeated {
+                        self.expand(&pre, depth + 1)?;
+                    }
+                }
+                Ok(())
             }
         }
-        seen.clear();
+
+        if !self.workflow.nodes.contains_key(node) {
+            return Err(anyhow::anyhow!("node not found: {}", node));
+        }
+        let mut walk = Walk {
+            compiler: self,
+            node,
+            edges: HashMap::new(),
+            reaches: HashMap::new(),
+            expanded: HashSet::from([target.to_owned()]),
+            steps: Vec::new(),
+        };
+        if target == node {
+            return Ok(Some(Vec::new()));
+        }
+        if !walk.reaches(target)? {
+            return Ok(None);
+        }
+        walk.expand(target, 0)?;
+        Ok(Some(walk.steps))
+    }
+
+    /// Determines all nodes that (transitively) depend on any of the given
+    /// nodes. Returns the direct and all dependents.
+    pub(crate) fn determine_dependents(&self, nodes: &HashSet<String>) -> Result<(Vec<String>, Vec<String>)> {
+        for n in nodes {
+            if !self.workflow.nodes.contains_key(n) {
+                return Err(anyhow::anyhow!("node not found: {}", n));
+            }
+        }
+
+        let mut reverse = HashMap::<String, Vec<String>>::new();
+        for (name, node) in &self.workflow.nodes {
+            for pre in self.resolve_pre(node)? {
+                reverse.entry(pre).or_default().push(name.clone());
+            }
+        }
+
+        let direct = nodes
+            .iter()
+            .flat_map(|n| reverse.get(n).into_iter().flatten())
+            .filter(|n| !nodes.contains(*n))
+            .cloned()
+            .sorted()
+            .dedup()
+            .collect_vec();
+
+        let mut seen = HashSet::<String>::new();
+        let mut pending = VecDeque::<String>::from_iter(nodes.iter().cloned());
+        while let Some(next) = pending.pop_front() {
+            for dependent in reverse.get(&next).into_iter().flatten() {
+                if seen.insert(dependent.clone()) {
+                    pending.push_back(dependent.clone());
+                }
+            }
+        }
+        let all = seen.into_iter().filter(|n| !nodes.contains(n)).sorted().collect_vec();
+
+        Ok((direct, all))
+    }
+
+    /// Groups the nodes of a dependency graph into stages in which every node
+    /// only depends on nodes of previous stages.
+    fn determine_stages(&self, graph: &HashMap<String, Vec<String>>) -> Result<Vec<HashSet<String>>> {
+        let mut map = graph.clone();
+        let mut seen = HashSet::<String>::new();
 
         let mut result = Vec::<HashSet<String>>::new();
-        while map.len() > 0 {
+        while !map.is_empty() {
             // This implementation SHOULD make use of the unstable hash_drain_filter feature
             // to use the drain_filter method on the hashmap but it's not allowed on stable
             // yet.
@@ -282,8 +755,8 @@ impl Compiler {
                 map.remove(&v.0);
             }
 
-            if leafs.len() == 0 {
-                return Err(anyhow::anyhow!("found recursion in dag"));
+            if leafs.is_empty() {
+                return Err(self.describe_cycle(&map));
             }
             let set = leafs.iter().map(|x| x.0.clone());
             seen.extend(set.clone());
@@ -292,4 +765,52 @@ impl Compiler {
 
         Ok(result)
     }
+
+    pub fn determine_order(&self, exec: &HashSet<String>) -> Result<Vec<HashSet<String>>> {
+        self.determine_stages(&self.determine_graph(exec)?)
+    }
+
+    /// Finds a cycle in the nodes that could not be staged (every one of them
+    /// waits for another one of them) and describes it including the selector
+    /// that produced each edge.
+    fn describe_cycle(&self, remaining: &HashMap<String, Vec<String>>) -> anyhow::Error {
+        // follow the first unresolved prerequisite until a node repeats
+        let mut path = vec![remaining.keys().min().unwrap().clone()];
+        let cycle = loop {
+            let current = path.last().unwrap();
+            let next = remaining[current]
+                .iter()
+                .filter(|v| remaining.contains_key(*v))
+                .min()
+                .unwrap();
+            if let Some(idx) = path.iter().position(|v| v == next) {
+                let mut cycle = path.split_off(idx);
+                cycle.push(next.clone());
+                break cycle;
+            }
+            path.push(next.clone());
+        };
+
+        let mut lines = vec![format!("found recursion in dag: {}", cycle.join(" -> "))];
+        for (from, to) in cycle.iter().tuple_windows() {
+            let selector = self.workflow.nodes[from].pre.iter().flatten().find(|sel| {
+                match sel {
+                    | NodeSelector::Name(v) => v == to,
+                    | NodeSelector::Regex(v) => {
+                        fancy_regex::Regex::new(v).is_ok_and(|r| r.is_match(to).unwrap_or_default())
+                    },
+                }
+            });
+            let via = match selector {
+                | Some(NodeSelector::Regex(v)) if from == to => {
+                    format!("pre regex \"{}\" matches the node itself", v)
+                },
+                | Some(NodeSelector::Regex(v)) => format!("pre regex \"{}\"", v),
+                | Some(NodeSelector::Name(_)) if from == to => "pre name references the node itself".to_owned(),
+                | _ => "pre name".to_owned(),
+            };
+            lines.push(format!("  {} -> {}: {}", from, to, via));
+        }
+        anyhow::anyhow!("{}", lines.join("\n"))
+    }
 }
@@ -0,0 +1,121 @@
+use {
+    anyhow::Result,
+    parking_lot::Mutex,
+    std::io::Write,
+};
+
+/// The version of the event schema. Incremented on breaking changes.
+pub(crate) const VERSION: &str = "1";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+/// A single line in the event stream of an execution.
+pub(crate) struct Event {
+    /// The version of the event schema.
+    pub version: String,
+    /// The point in time the event occurred at (RFC 3339).
+    #[schemars(with = "String")]
+    pub time: chrono::DateTime<chrono::Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "event")]
+/// The kind of an event and its payload.
+pub(crate) enum EventKind {
+    /// The execution of a plan started.
+    PlanStarted {
+        /// All nodes in the plan.
+        nodes: Vec<String>,
+        /// The number of stages in the plan.
+        stages: usize,
+    },
+    /// The first node of a stage started.
+    StageStarted {
+        /// The index of the stage.
+        stage: usize,
+        /// All nodes in the stage.
+        nodes: Vec<String>,
+    },
+    /// A node was not executed because it is up to date.
+    NodeUpToDate { node: String },
+    /// The outputs of a node were restored from the cache instead of executing
+    /// it.
+    NodeRestored {
+        node: String,
+        /// Fingerprint of the node that keys the cache entry.
+        fingerprint: String,
+    },
+    /// A task was not executed for a matrix entry because it succeeded in a
+    /// previous execution of the same plan (`--resume`).
+    TaskResumed {
+        node: String,
+        /// Coordinates of the matrix entry.
+        cell: Vec<u8>,
+        /// Index of the task in the node.
+        task: usize,
+    },
+    /// A task started for a matrix entry.
+    TaskStarted {
+        node: String,
+        /// Coordinates of the matrix entry.
+        cell: Vec<u8>,
+        /// Index of the task in the node.
+        task: usize,
+        /// Attempt number, starting at 1.
+        attempt: usize,
+        /// Process ID of the task.
+        pid: u32,
+    },
+    /// A task finished for a matrix entry.
+    TaskFinished {
+        node: String,
+        /// Coordinates of the matrix entry.
+        cell: Vec<u8>,
+        /// Index of the task in the node.
+        task: usize,
+        /// Attempt number, starting at 1.
+        attempt: usize,
+        /// Process ID of the task.
+        pid: u32,
+        /// Exit code of the task. Not set if it was terminated by a signal or
+        /// timed out.
+        exit_code: Option<i32>,
+        /// Human readable outcome.
+        outcome: String,
+        /// Runtime in milliseconds.
+        duration_ms: u64,
+    },
+    /// The execution of the plan finished.
+    RunFinished {
+        success: bool,
+        /// Runtime in milliseconds.
+        duration_ms: u64,
+    },
+}
+
+/// Writes events as newline delimited JSON.
+pub(crate) struct EventWriter {
+    sink: Mutex<Box<dyn Write+Send>>,
+}
+
+impl EventWriter {
+    pub fn new(sink: Box<dyn Write+Send>) -> Self {
+        Self { sink: Mutex::new(sink) }
+    }
+
+    pub fn emit(&self, kind: EventKind) -> Result<()> {
+        let event = Event {
+            version: VERSION.to_owned(),
+            time: chrono::Utc::now(),
+            kind,
+        };
+        let mut line = serde_json::to_vec(&event)?;
+        line.push(b'\n');
+        let mut sink = self.sink.lock();
+        sink.write_all(&line)?;
+        sink.flush()?;
+        Ok(())
+    }
+}
@@ -1,148 +1,1390 @@
 use {
-    crate::plan,
+    crate::{
+        cache::Cache,
+        events::{
+            EventKind,
+            EventWriter,
+        },
+        junit::TaskReport,
+        plan,
+        state::{
+            self,
+            Fingerprints,
+            Progress,
+            WorkItem,
+        },
+        workflow::{
+            AllowFailure,
+            Retry,
+        },
+    },
     anyhow::Result,
+    crossterm::style::{
+        Color,
+        Stylize,
+    },
+    itertools::Itertools,
+    parking_lot::Mutex,
     std::{
-        collections::HashMap,
-        process::Stdio,
+        collections::{
+            BTreeMap,
+            HashMap,
+            HashSet,
+            VecDeque,
+        },
+        hash::{
+            Hash,
+            Hasher,
+        },
+        io::{
+            BufRead,
+            BufReader,
+            Read,
+            Write,
+        },
+        os::{
+            fd::{
+                AsFd,
+                AsRawFd,
+            },
+            unix::process::{
+                CommandExt,
+                ExitStatusExt,
+            },
+        },
+        path::{
+            Path,
+            PathBuf,
+        },
+        process::{
+            ExitStatus,
+            Stdio,
+        },
+        sync::{
+            atomic::{
+                AtomicBool,
+                Ordering,
+            },
+            Arc,
+            OnceLock,
+        },
+        time::{
+            Duration,
+            Instant,
+        },
     },
     threadpool::ThreadPool,
 };
 
+/// Env vars with matching names are masked in dry runs.
+const SECRET_PATTERN: &str = r"(?i)(secret|token|passw(or)?d|credential|api_?key|private_?key|(^|_)key$)";
+
+/// Placeholder for masked values.
+const MASK: &str = "********";
+
+/// Time granted to interrupted tasks to shut down before they are killed.
+const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Time the output of a task is still read after it exited. Processes it left
+/// running in the background may keep writing to its pipes indefinitely.
+const DRAIN_LIMIT: Duration = Duration::from_millis(500);
+
+/// Number of trailing output bytes per stream that are kept for reports.
+const TAIL_LIMIT: usize = 8 * 1024;
+
+/// Maximum number of bytes of a line that is buffered before it is prefixed
+/// and forwarded. Longer lines are forwarded in several parts.
+pub(crate) const LINE_LIMIT: usize = 8 * 1024;
+
 #[derive(Debug, Clone)]
 pub(crate) struct OutputMode {
     pub stderr: bool,
     pub stdout: bool,
+    pub style: OutputStyle,
+    /// Writes the STDOUT of the tasks to STDERR, e.g. because STDOUT carries
+    /// events.
+    pub redirect_stdout: bool,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum OutputStyle {
+    /// Tasks write to the STDOUT/STDERR of this process directly.
+    Inherit,
+    /// Every line is prefixed with the node name and matrix cell.
+    Prefix { color: bool },
+    /// The output of every task is buffered and printed as one block once the
+    /// task finished.
+    Grouped,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum FailureMode {
+    /// Terminate all running tasks and stop on the first failure.
+    FailFast,
+    /// Keep executing every node that does not depend on a failed node and
+    /// report all failures at the end.
+    KeepGoing,
 }
 
 pub(crate) struct ExecutionEngine {
     pub output: OutputMode,
+    pub failure: FailureMode,
+    pub events: Option<Arc<EventWriter>>,
+    pub junit: Option<String>,
+    pub resume: bool,
+    /// Directory that holds fingerprints, cached outputs and the progress of
+    /// executions.
+    pub state_dir: PathBuf,
+}
+
+/// A fully resolved work item as printed by a dry run.
+#[derive(Debug, serde::Serialize)]
+struct ResolvedWork {
+    stage: usize,
+    node: String,
+    cell: Vec<u8>,
+    task: usize,
+    shell: String,
+    workdir: Option<String>,
+    env: BTreeMap<String, String>,
+    command: String,
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    timeout: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry: Option<Retry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_failure: Option<AllowFailure>,
+}
+
+#[derive(Debug)]
+struct Work {
+    node: String,
+    cell: Vec<u8>,
+    task: usize,
+
+    workdir: Option<String>,
+    env: HashMap<String, String>,
+    shell: String,
+    command: String,
+    timeout: Option<Duration>,
+    retry: Option<Retry>,
+    allow_failure: Option<AllowFailure>,
+}
+
+/// A point in time at which a running task is killed, alongside the limit
+/// that it was derived from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Deadline {
+    at: Instant,
+    limit: Duration,
+    scope: &'static str,
+}
+
+impl Deadline {
+    fn new(limit: Duration, scope: &'static str) -> Self {
+        Self::since(Instant::now(), limit, scope)
+    }
+
+    fn since(start: Instant, limit: Duration, scope: &'static str) -> Self {
+        Self {
+            at: start + limit,
+            limit,
+            scope,
+        }
+    }
+
+    fn earliest(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            | (Some(a), Some(b)) => Some(if a.at <= b.at { a } else { b }),
+            | (a, b) => a.or(b),
+        }
+    }
+}
+
+/// The result of a single attempt of a work item.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Success,
+    Code(i32),
+    Signal(i32),
+    TimedOut(Deadline),
+}
+
+impl Outcome {
+    fn code(&self) -> Option<i32> {
+        match self {
+            | Outcome::Success => Some(0),
+            | Outcome::Code(v) => Some(*v),
+            | _ => None,
+        }
+    }
+
+    fn retryable(&self, retry: &Retry) -> bool {
+        match (self, &retry.exit_codes) {
+            | (Outcome::Success, _) => false,
+            | (Outcome::Code(v), Some(codes)) => codes.contains(v),
+            | (_, Some(_)) => false,
+            | (_, None) => true,
+        }
+    }
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            | Outcome::Success => write!(f, "succeeded"),
+            | Outcome::Code(v) => write!(f, "failed to execute with code {}", v),
+            | Outcome::Signal(v) => write!(f, "was terminated by signal {}", v),
+            | Outcome::TimedOut(v) => {
+                write!(
+                    f,
+                    "timed out after {} ({} timeout)",
+                    humantime_serde::re::humantime::format_duration(v.limit),
+                    v.scope
+                )
+            },
+        }
+    }
+}
+
+/// Marks a task that was not (fully) executed because the run was cancelled.
+#[derive(Debug)]
+struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl Work {
+    /// Human readable identification of this work item.
+    fn label(&self) -> String {
+        if self.cell.is_empty() {
+            format!("{} task={}", self.node, self.task)
+        } else {
+            format!("{} cell={} task={}", self.node, self.cell.iter().join(","), self.task)
+        }
+    }
+}
+
+/// Keeps track of the process groups of all running tasks so they can be
+/// terminated as a whole.
+#[derive(Debug, Default)]
+struct Supervisor {
+    cancelled: bool,
+    /// Running process groups and the label of the task they belong to.
+    groups: HashMap<u32, String>,
+    /// The signal that interrupted the run and the tasks that were running.
+    interrupted: Option<(i32, Vec<String>)>,
+}
+
+impl Supervisor {
+    /// Cancels the run and kills every running process group. Tasks that are
+    /// about to be spawned will not start anymore.
+    fn cancel(&mut self) {
+        self.cancelled = true;
+        self.signal(libc::SIGKILL);
+    }
+
+    /// Cancels the run and forwards the signal to every running process group.
+    fn interrupt(&mut self, signal: i32) {
+        self.cancelled = true;
+        if self.interrupted.is_none() {
+            self.interrupted = Some((signal, self.groups.values().cloned().sorted().collect()));
+        }
+        self.signal(signal);
+    }
+
+    fn signal(&self, signal: i32) {
+        for pgid in self.groups.keys() {
+            unsafe {
+                libc::kill(-(*pgid as libc::pid_t), signal);
+            }
+        }
+    }
+}
+
+/// State that is shared between the engine and all workers of one execution.
+struct Context {
+    output: OutputMode,
+    supervisor: Mutex<Supervisor>,
+    events: Option<Arc<EventWriter>>,
+    /// Reports of all attempts, only collected if a report is written.
+    reports: Option<Mutex<Vec<TaskReport>>>,
+    progress: Mutex<Progress>,
+}
+
+impl Context {
+    fn emit(&self, kind: EventKind) {
+        if let Some(events) = &self.events {
+            if let Err(e) = events.emit(kind) {
+                eprintln!("warning: failed to write event: {}", e);
+            }
+        }
+    }
+}
+
+enum Freshness {
+    UpToDate,
+    /// Needs to be executed. Contains the current fingerprint if the node
+    /// declares inputs.
+    Outdated(Option<String>),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn write(self, data: &[u8]) {
+        let _ = match self {
+            | Stream::Stdout => {
+                let mut sink = std::io::stdout().lock();
+                sink.write_all(data).and_then(|_| sink.flush())
+            },
+            | Stream::Stderr => {
+                let mut sink = std::io::stderr().lock();
+                sink.write_all(data).and_then(|_| sink.flush())
+            },
+        };
+    }
+}
+
+/// What happens to the output of a task besides keeping its tail.
+enum Forward {
+    /// Dropped.
+    Discard,
+    /// Written to the stream as is.
+    Raw,
+    /// Written to the stream line by line with the given prefix.
+    Prefix(String),
+    /// Appended to the buffer.
+    Capture(Arc<Mutex<Captured>>),
+}
+
+/// The output of both streams of a task in the order it was written.
+#[derive(Debug, Default)]
+pub(crate) struct Captured {
+    pub data: Vec<u8>,
+    last: Option<Stream>,
+}
+
+impl Captured {
+    /// Appends a chunk of output. Output of one stream always starts on a new
+    /// line if the other stream wrote last.
+    pub fn append(&mut self, stream: Stream, chunk: &[u8]) {
+        if self.last.is_some_and(|v| v != stream) && !self.data.is_empty() && !self.data.ends_with(b"\n") {
+            self.data.push(b'\n');
+        }
+        self.data.extend_from_slice(chunk);
+        self.last = Some(stream);
+    }
+}
+
+/// A pipe of a task that is read until the writing end is closed or, once the
+/// task exited, until no more output is pending. Processes the task left
+/// running in the background may keep the pipe open, they must not keep the
+/// task from finishing. Their output is read for at most `DRAIN_LIMIT` after
+/// the task exited.
+struct Pipe<R> {
+    source: R,
+    exited: Arc<AtomicBool>,
+    drain_until: Option<Instant>,
+    /// Set if output was still pending when `DRAIN_LIMIT` passed.
+    abandoned: Arc<AtomicBool>,
+}
+
+impl<R> Pipe<R> {
+    fn new(source: R, exited: &Arc<AtomicBool>, abandoned: &Arc<AtomicBool>) -> Self {
+        Self {
+            source,
+            exited: exited.clone(),
+            drain_until: None,
+            abandoned: abandoned.clone(),
+        }
+    }
+}
+
+impl<R: Read+AsRawFd> Read for Pipe<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let exited = self.exited.load(Ordering::Acquire);
+            if exited {
+                let until = *self.drain_until.get_or_insert_with(|| Instant::now() + DRAIN_LIMIT);
+                if Instant::now() >= until {
+                    self.abandoned.store(true, Ordering::Release);
+                    return Ok(0);
+                }
+            }
+            let mut fd = libc::pollfd {
+                fd: self.source.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            match unsafe { libc::poll(&mut fd, 1, if exited { 0 } else { 50 }) } {
+                | n if n < 0 => {
+                    let e = std::io::Error::last_os_error();
+                    if e.kind() != std::io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                },
+                | 0 if exited => return Ok(0),
+                | 0 => {},
+                | _ => return self.source.read(buf),
+            }
+        }
+    }
+}
+
+/// Decides which node is started next. A node becomes ready once all of its
+/// prerequisites finished, simultaneously ready nodes are started in stage
+/// order.
+pub(crate) struct Scheduler<'a> {
+    failure: FailureMode,
+    /// Number of unfinished prerequisites per node.
+    blocked: HashMap<&'a str, usize>,
+    dependents: HashMap<&'a str, Vec<&'a str>>,
+    ready: VecDeque<&'a str>,
+    /// Nodes that are not executed because a prerequisite failed.
+    skipped: HashSet<&'a str>,
+    /// Whether any node failed.
+    failed: bool,
+}
+
+impl<'a> Scheduler<'a> {
+    pub fn new(plan: &'a plan::ExecutionPlan, failure: FailureMode) -> Result<Self> {
+        plan.validate()?;
+        let mut blocked = HashMap::<&str, usize>::new();
+        let mut dependents = HashMap::<&str, Vec<&str>>::new();
+        for (name, node) in &plan.nodes {
+            let pre = match &node.pre {
+                | Some(v) => v.iter().map(|v| v.as_str()).collect_vec(),
+                // plans of older versions only order their stages
+                | None => {
+                    plan.stages
+                        .iter()
+                        .take_while(|s| !s.nodes.contains(name))
+                        .last()
+                        .map(|s| s.nodes.iter().map(|v| v.as_str()).collect_vec())
+                        .unwrap_or_default()
+                },
+            };
+            blocked.insert(name, pre.len());
+            for pre in pre {
+                if !plan.nodes.contains_key(pre) {
+                    return Err(anyhow::anyhow!("node {} depends on unknown node {}", name, pre));
+                }
+                dependents.entry(pre).or_default().push(name);
+            }
+        }
+
+        let ready = plan
+            .stages
+            .iter()
+            .flat_map(|s| s.nodes.iter())
+            .filter(|n| blocked[n.as_str()] == 0)
+            .map(|n| n.as_str())
+            .collect::<VecDeque<_>>();
+        Ok(Self {
+            failure,
+            blocked,
+            dependents,
+            ready,
+            skipped: HashSet::new(),
+            failed: false,
+        })
+    }
+
+    /// The next node to start. Fail fast does not start anything new after a
+    /// failure.
+    pub fn next(&mut self) -> Option<&'a str> {
+        if self.failed && self.failure == FailureMode::FailFast {
+            return None;
+        }
+        self.ready.pop_front()
+    }
+
+    /// Marks the node as succeeded and queues all dependents that are not
+    /// waiting for any other node anymore.
+    pub fn succeed(&mut self, name: &'a str) {
+        for dependent in self.dependents.get(name).into_iter().flatten() {
+            let cnt = self.blocked.get_mut(dependent).unwrap();
+            *cnt -= 1;
+            if *cnt == 0 {
+                self.ready.push_back(dependent);
+            }
+        }
+    }
+
+    /// Marks the node as failed (or cancelled) and all of its transitive
+    /// dependents as skipped.
+    pub fn fail(&mut self, name: &'a str) {
+        self.failed = true;
+        let mut pending = vec![name];
+        while let Some(name) = pending.pop() {
+            for dependent in self.dependents.get(name).into_iter().flatten() {
+                if self.skipped.insert(dependent) {
+                    pending.push(dependent);
+                }
+            }
+        }
+    }
+
+    /// Nodes that are not executed because a prerequisite failed.
+    pub fn skipped(&self) -> &HashSet<&'a str> {
+        &self.skipped
+    }
+}
+
+/// Forwards SIGINT and SIGTERM to all running tasks. Tasks that are still
+/// running after the grace period are killed. Another signal after the grace
+/// period terminates neomake right away with the conventional exit code.
+fn forward_signals(ctx: Arc<Context>) -> Result<(signal_hook::iterator::Handle, std::thread::JoinHandle<()>)> {
+    let mut signals = signal_hook::iterator::Signals::new([libc::SIGINT, libc::SIGTERM])?;
+    let handle = signals.handle();
+    let thread = std::thread::spawn(move || {
+        let mut interrupted = false;
+        for signal in signals.forever() {
+            if interrupted {
+                ctx.supervisor.lock().cancel();
+                std::process::exit(128 + signal);
+            }
+            interrupted = true;
+            ctx.supervisor.lock().interrupt(signal);
+
+            let deadline = Instant::now() + GRACE_PERIOD;
+            while Instant::now() < deadline && !ctx.supervisor.lock().groups.is_empty() {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            ctx.supervisor.lock().cancel();
+        }
+    });
+    Ok((handle, thread))
 }
 
 impl ExecutionEngine {
-    pub fn new(output: OutputMode) -> Self {
-        Self { output }
+    pub fn new(output: OutputMode, failure: FailureMode) -> Self {
+        Self {
+            output,
+            failure,
+            events: None,
+            junit: None,
+            resume: false,
+            state_dir: PathBuf::from(state::DEFAULT_STATE_DIR),
+        }
+    }
+
+    /// Keeps all local state in the given directory instead of `.neomake`.
+    pub fn with_state_dir(mut self, path: &Path) -> Self {
+        self.state_dir = path.to_owned();
+        self
+    }
+
+    /// Writes the events of every execution to the given writer.
+    pub fn with_events(mut self, events: EventWriter) -> Self {
+        self.events = Some(Arc::new(events));
+        self
     }
 
+    /// Writes a JUnit XML report of every execution to the given path.
+    pub fn with_junit(mut self, path: &str) -> Self {
+        self.junit = Some(path.to_owned());
+        self
+    }
+
+    /// Skips all work items that succeeded in a previous execution of the
+    /// same plan.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Executes the plan by starting every node as soon as all of its
+    /// prerequisites have finished. The stages of the plan are only used to
+    /// determine the order in which simultaneously ready nodes are started.
     pub fn execute(&self, plan: &plan::ExecutionPlan, workers: usize) -> Result<()> {
-        #[derive(Debug)]
-        struct Work {
-            workdir: Option<String>,
-            env: HashMap<String, String>,
-            shell: String,
-            command: String,
-        }
-
-        for stage in &plan.stages {
-            let pool = ThreadPool::new(workers);
-            let (signal_tx, signal_rx) = std::sync::mpsc::channel::<Result<()>>();
-            let mut signal_cnt = 0;
-
-            let nodes = stage.nodes.iter().map(|v| plan.nodes.get(v).unwrap());
-            for node in nodes {
-                let mut batches = Vec::<Vec<Work>>::new();
-                let mut current_batch = Vec::<Work>::new();
-
-                for invoke in &node.invocations {
-                    for task in &node.tasks {
-                        let workdir = if let Some(workdir) = &task.workdir {
-                            Some(workdir.to_owned())
-                        } else if let Some(workdir) = &node.workdir {
-                            Some(workdir.to_owned())
-                        } else {
-                            None
-                        };
+        plan.validate()?;
+        let ctx = Arc::new(Context {
+            output: self.output.clone(),
+            supervisor: Mutex::new(Supervisor::default()),
+            events: self.events.clone(),
+            reports: self.junit.as_ref().map(|_| Mutex::new(Vec::new())),
+            progress: Mutex::new(Progress::open(&self.state_dir, plan, self.resume)?),
+        });
+        let started = Instant::now();
+        ctx.emit(EventKind::PlanStarted {
+            nodes: plan.nodes.keys().cloned().sorted().collect(),
+            stages: plan.stages.len(),
+        });
+        let res = self.run(plan, workers, &ctx);
+        ctx.emit(EventKind::RunFinished {
+            success: res.is_ok(),
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+        if res.is_ok() {
+            if let Err(e) = ctx.progress.lock().finish() {
+                eprintln!("warning: failed to remove progress: {}", e);
+            }
+        }
+        if let (Some(path), Some(reports)) = (&self.junit, &ctx.reports) {
+            // the report is written regardless of the result of the execution
+            let written = std::fs::write(path, crate::junit::render(&reports.lock()));
+            match (&res, written) {
+                | (_, Ok(())) => {},
+                | (Ok(_), Err(e)) => return Err(anyhow::anyhow!("failed to write junit report to {}: {}", path, e)),
+                | (Err(_), Err(e)) => eprintln!("warning: failed to write junit report to {}: {}", path, e),
+            }
+        }
+        res
+    }
 
-                        let shell = if let Some(shell) = &task.shell {
-                            shell.to_owned()
-                        } else if let Some(shell) = &node.shell {
-                            shell.to_owned()
+    /// Resolves every work item of the plan in stage order without executing
+    /// anything. Values of env vars that look like secrets are masked, also
+    /// where they occur in commands.
+    pub fn dry_run(&self, plan: &plan::ExecutionPlan) -> Result<String> {
+        plan.validate()?;
+        let secret = fancy_regex::Regex::new(SECRET_PATTERN)?;
+        let mut items = Vec::<ResolvedWork>::new();
+        for (idx, stage) in plan.stages.iter().enumerate() {
+            for name in &stage.nodes {
+                for work in self.batches(plan, name, &plan.nodes[name]).into_iter().flatten() {
+                    let mut secrets = Vec::<String>::new();
+                    let mut env = BTreeMap::<String, String>::new();
+                    for (k, v) in work.env {
+                        if secret.is_match(&k)? {
+                            if !v.is_empty() {
+                                secrets.push(v);
+                            }
+                            env.insert(k, MASK.to_owned());
                         } else {
-                            "/bin/sh -c".to_owned()
-                        };
-
-                        let mut env = plan.env.clone();
-                        env.extend(node.env.clone());
-                        env.extend(invoke.env.clone());
-                        env.extend(task.env.clone());
-
-                        current_batch.push(Work {
-                            command: task.cmd.clone(),
-                            env,
-                            shell,
-                            workdir,
-                        })
+                            env.insert(k, v);
+                        }
                     }
+                    let mut command = work.command;
+                    for v in secrets {
+                        command = Self::mask(&command, &v);
+                    }
+                    items.push(ResolvedWork {
+                        stage: idx,
+                        node: work.node,
+                        cell: work.cell,
+                        task: work.task,
+                        shell: work.shell,
+                        workdir: work.workdir,
+                        env,
+                        command,
+                        timeout: work.timeout,
+                        retry: work.retry,
+                        allow_failure: work.allow_failure,
+                    });
                 }
+            }
+        }
+        Ok(serde_yaml::to_string(&items)?)
+    }
 
-                // add all items as individual batches if parallel is allowed
-                if node.parallel {
-                    signal_cnt += current_batch.len();
-                    for w in current_batch {
-                        batches.push(vec![w]);
+    /// Masks every occurrence of the secret that is not part of a longer word
+    /// so that short values do not mask unrelated parts of the command.
+    fn mask(command: &str, secret: &str) -> String {
+        let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let mut masked = String::with_capacity(command.len());
+        let mut last = 0;
+        for (idx, _) in command.match_indices(secret) {
+            let end = idx + secret.len();
+            if word(command[..idx].chars().next_back()) || word(command[end..].chars().next()) {
+                continue;
+            }
+            masked.push_str(&command[last..idx]);
+            masked.push_str(MASK);
+            last = end;
+        }
+        masked.push_str(&command[last..]);
+        masked
+    }
+
+    fn run(&self, plan: &plan::ExecutionPlan, workers: usize, ctx: &Arc<Context>) -> Result<()> {
+        let pool = ThreadPool::new(workers);
+        let (signal_tx, signal_rx) = std::sync::mpsc::channel::<(String, Vec<String>, Vec<String>, Result<()>)>();
+        let (signals, signal_thread) = forward_signals(ctx.clone())?;
+
+        let mut scheduler = Scheduler::new(plan, self.failure)?;
+        // outstanding batches per running node
+        let mut running = HashMap::<&str, usize>::new();
+        // nodes with at least one failed task
+        let mut failed = HashSet::<&str>::new();
+        // nodes that were interrupted because of a failure elsewhere
+        let mut cancelled = HashSet::<&str>::new();
+        let mut done = 0_usize;
+        let mut errs = Vec::<String>::new();
+        // allowed failures and problems that do not fail the run
+        let mut warnings = Vec::<String>::new();
+        let mut stages_started = HashSet::<usize>::new();
+        let mut fingerprints = Fingerprints::load(&self.state_dir);
+        let cache = Cache::new(&self.state_dir);
+        // fingerprints of running nodes that are stored once they succeed
+        let mut pending = HashMap::<&str, String>::new();
+        // nodes with allowed failures are never considered up to date
+        let mut degraded = HashSet::<&str>::new();
+
+        loop {
+            while let Some(name) = scheduler.next() {
+                match Self::freshness(plan, name, &fingerprints) {
+                    | Ok(Freshness::UpToDate) => {
+                        eprintln!("node {}: up to date", name);
+                        ctx.emit(EventKind::NodeUpToDate { node: name.to_owned() });
+                        done += 1;
+                        scheduler.succeed(name);
+                        continue;
+                    },
+                    | Ok(Freshness::Outdated(Some(fingerprint))) if !plan.nodes[name].outputs.is_empty() => {
+                        match cache.restore(&fingerprint, &plan.nodes[name]) {
+                            | Ok(true) => {
+                                eprintln!("node {}: restored from cache", name);
+                                ctx.emit(EventKind::NodeRestored {
+                                    node: name.to_owned(),
+                                    fingerprint: fingerprint.clone(),
+                                });
+                                if let Err(e) = fingerprints.set(name, fingerprint) {
+                                    warnings.push(format!("node {}: failed to store fingerprint: {}", name, e));
+                                }
+                                done += 1;
+                                scheduler.succeed(name);
+                                continue;
+                            },
+                            | Ok(false) => {},
+                            | Err(e) => warnings.push(format!("node {}: failed to restore from cache: {}", name, e)),
+                        }
+                        pending.insert(name, fingerprint);
+                    },
+                    | Ok(Freshness::Outdated(fingerprint)) => {
+                        if let Some(v) = fingerprint {
+                            pending.insert(name, v);
+                        }
+                    },
+                    | Err(e) => {
+                        failed.insert(name);
+                        errs.push(format!("node {}: {}", name, e));
+                        done += 1;
+                        scheduler.fail(name);
+                        if self.failure == FailureMode::FailFast {
+                            ctx.supervisor.lock().cancel();
+                        }
+                        continue;
+                    },
+                }
+
+                let batches = self.batches(plan, name, &plan.nodes[name]);
+                if batches.is_empty() {
+                    done += 1;
+                    scheduler.succeed(name);
+                    continue;
+                }
+
+                if let Some(idx) = plan.stages.iter().position(|s| s.nodes.iter().any(|n| n == name)) {
+                    if stages_started.insert(idx) {
+                        ctx.emit(EventKind::StageStarted {
+                            stage: idx,
+                            nodes: plan.stages[idx].nodes.clone(),
+                        });
                     }
-                } else {
-                    signal_cnt += 1;
-                    batches.push(current_batch);
                 }
 
-                let output = self.output.clone();
-                // executes matrix entry
+                running.insert(name, batches.len());
+                let timeout = plan.nodes[name].timeout;
+                // shared by all batches of the node, set by the first one that runs
+                let node_started = Arc::new(OnceLock::<Instant>::new());
                 for batch in batches {
                     let t_tx = signal_tx.clone();
+                    let ctx = ctx.clone();
+                    let node = name.to_owned();
+                    let node_started = node_started.clone();
                     pool.execute(move || {
-                        let res = move || -> Result<()> {
-                            for work in batch {
-                                let mut shell = work.shell.split_whitespace();
-                                let mut cmd_proc = std::process::Command::new(shell.next().unwrap());
-                                while let Some(v) = shell.next() {
-                                    cmd_proc.arg(v);
-                                }
-                                cmd_proc.envs(work.env);
-                                if let Some(w) = work.workdir {
-                                    cmd_proc.current_dir(w);
-                                }
-                                cmd_proc.arg(&work.command);
-                                cmd_proc.stdin(Stdio::null());
+                        let mut allowed = Vec::<String>::new();
+                        let mut warnings = Vec::<String>::new();
+                        // a panicking worker must still report back, the run would wait forever
+                        // otherwise
+                        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                            Self::run_batch(batch, timeout, &node_started, &ctx, &mut allowed, &mut warnings)
+                        }))
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("worker panicked")));
+                        t_tx.send((node, allowed, warnings, res)).expect("send failed");
+                    });
+                }
+            }
 
-                                if !output.stdout {
-                                    cmd_proc.stdout(Stdio::null());
-                                }
-                                if !output.stderr {
-                                    cmd_proc.stderr(Stdio::null());
-                                }
+            if running.is_empty() {
+                break;
+            }
 
-                                let output = cmd_proc.spawn()?.wait_with_output()?;
-
-                                match output.status.code().unwrap() {
-                                    | 0 => Ok(()),
-                                    | v => {
-                                        Err(anyhow::anyhow!(
-                                            "command: {} failed to execute with code {}",
-                                            work.command,
-                                            v
-                                        ))
-                                    },
-                                }?;
+            let (node, allowed, batch_warnings, res) = signal_rx.recv()?;
+            let name = plan.nodes.get_key_value(&node).unwrap().0.as_str();
+            if !allowed.is_empty() {
+                degraded.insert(name);
+            }
+            warnings.extend(
+                allowed
+                    .into_iter()
+                    .chain(batch_warnings)
+                    .map(|w| format!("node {}: {}", name, w)),
+            );
+            match res {
+                | Ok(()) => {},
+                | Err(e) if e.downcast_ref::<Cancelled>().is_some() => {
+                    cancelled.insert(name);
+                },
+                | Err(e) => {
+                    failed.insert(name);
+                    errs.push(format!("node {}: {}", name, e));
+                    if self.failure == FailureMode::FailFast {
+                        ctx.supervisor.lock().cancel();
+                    }
+                },
+            }
+
+            let outstanding = running.get_mut(name).unwrap();
+            *outstanding -= 1;
+            if *outstanding == 0 {
+                running.remove(name);
+                done += 1;
+                if failed.contains(name) || cancelled.contains(name) {
+                    scheduler.fail(name);
+                } else {
+                    if let Some(fingerprint) = pending.remove(name).filter(|_| !degraded.contains(name)) {
+                        if !plan.nodes[name].outputs.is_empty() {
+                            if let Err(e) = cache.store(name, &fingerprint, &plan.nodes[name]) {
+                                warnings.push(format!("node {}: failed to cache outputs: {}", name, e));
                             }
-                            Ok(())
-                        }();
-                        t_tx.send(res).expect("send failed");
-                    });
+                        }
+                        if let Err(e) = fingerprints.set(name, fingerprint) {
+                            warnings.push(format!("node {}: failed to store fingerprint: {}", name, e));
+                        }
+                    }
+                    scheduler.succeed(name);
                 }
             }
+        }
+
+        signals.close();
+        signal_thread.join().expect("signal thread panicked");
+
+        for w in &warnings {
+            eprintln!("warning: {}", w);
+        }
 
-            let errs = signal_rx
-                .iter()
-                .take(signal_cnt)
-                .filter(|x| x.is_err())
-                .map(|x| x.expect_err("expecting an err"))
-                .map(|v| v.to_string())
-                .collect::<Vec<_>>();
-            if errs.len() > 0 {
-                return Err(anyhow::anyhow!("{}", errs.join("\n")).into());
-                // abort at this stage
+        if let Some((signal, tasks)) = ctx.supervisor.lock().interrupted.take() {
+            let signal = match signal {
+                | libc::SIGINT => "SIGINT",
+                | libc::SIGTERM => "SIGTERM",
+                | _ => "unknown signal",
+            };
+            let mut report = vec![format!("interrupted by {}", signal)];
+            report.extend(tasks.into_iter().map(|t| format!("interrupted: {}", t)));
+            report.extend(errs);
+            return Err(anyhow::anyhow!("{}", report.join("\n")));
+        }
+        if !errs.is_empty() {
+            let mut report = errs;
+            cancelled.retain(|v| !failed.contains(v));
+            if !cancelled.is_empty() {
+                report.push(format!("cancelled: {}", cancelled.into_iter().sorted().join(", ")));
             }
+            if self.failure == FailureMode::KeepGoing && !scheduler.skipped().is_empty() {
+                report.push(format!(
+                    "skipped due to failed prerequisites: {}",
+                    scheduler.skipped().iter().sorted().join(", ")
+                ));
+            }
+            return Err(anyhow::anyhow!("{}", report.join("\n")));
+        }
+        if done != plan.nodes.len() {
+            return Err(anyhow::anyhow!("found recursion in dag"));
         }
         Ok(())
     }
+
+    /// Determines whether the node is up to date, i.e. it declares inputs, its
+    /// fingerprint equals the one of its last successful execution and all of
+    /// its outputs exist.
+    fn freshness(plan: &plan::ExecutionPlan, name: &str, fingerprints: &Fingerprints) -> Result<Freshness> {
+        let node = &plan.nodes[name];
+        match state::fingerprint(plan, node)? {
+            | Some(v) if fingerprints.get(name) == Some(v.as_str()) && state::outputs_exist(node)? => {
+                Ok(Freshness::UpToDate)
+            },
+            | v => Ok(Freshness::Outdated(v)),
+        }
+    }
+
+    /// Splits a node into batches of work. Every batch is executed sequentially
+    /// on one worker. Parallel nodes yield one batch per (matrix entry, task).
+    fn batches(&self, plan: &plan::ExecutionPlan, name: &str, node: &plan::Node) -> Vec<Vec<Work>> {
+        let mut batches = Vec::<Vec<Work>>::new();
+        let mut current_batch = Vec::<Work>::new();
+
+        for (inv_idx, invoke) in node.invocations.iter().enumerate() {
+            for (idx, task) in node.tasks.iter().enumerate() {
+                let workdir = if let Some(workdir) = &task.workdir {
+                    Some(workdir.to_owned())
+                } else {
+                    node.workdir.as_ref().map(|workdir| workdir.to_owned())
+                };
+
+                let shell = if let Some(shell) = &task.shell {
+                    shell.to_owned()
+                } else if let Some(shell) = &node.shell {
+                    shell.to_owned()
+                } else {
+                    "/bin/sh -c".to_owned()
+                };
+
+                let mut env = plan.env.clone();
+                env.extend(node.env.clone());
+                env.extend(invoke.env.clone());
+                env.extend(task.env.clone());
+
+                current_batch.push(Work {
+                    node: name.to_owned(),
+                    cell: invoke.cell.clone(),
+                    task: idx,
+                    command: task.cmd(inv_idx).to_owned(),
+                    env,
+                    shell,
+                    workdir,
+                    timeout: task.timeout,
+                    retry: task.retry.clone().or_else(|| node.retry.clone()),
+                    allow_failure: task.allow_failure.clone().or_else(|| node.allow_failure.clone()),
+                })
+            }
+        }
+
+        // add all items as individual batches if parallel is allowed
+        if node.parallel {
+            for w in current_batch {
+                batches.push(vec![w]);
+            }
+        } else {
+            batches.push(current_batch);
+        }
+        batches
+    }
+
+    /// Executes the work items of a batch sequentially. The timeout of the node
+    /// starts once the first batch of the node is picked up by a worker.
+    /// Allowed failures are collected in `allowed`, other problems that do not
+    /// fail the batch in `warnings`.
+    fn run_batch(
+        batch: Vec<Work>,
+        node_timeout: Option<Duration>,
+        node_started: &OnceLock<Instant>,
+        ctx: &Context,
+        allowed: &mut Vec<String>,
+        warnings: &mut Vec<String>,
+    ) -> Result<()> {
+        let node_deadline = node_timeout.map(|v| Deadline::since(*node_started.get_or_init(Instant::now), v, "node"));
+        for work in batch {
+            let item = WorkItem {
+                node: work.node.clone(),
+                cell: work.cell.clone(),
+                task: work.task,
+            };
+            if ctx.progress.lock().is_completed(&item) {
+                eprintln!("{}: completed in previous execution", work.label());
+                ctx.emit(EventKind::TaskResumed {
+                    node: work.node.clone(),
+                    cell: work.cell.clone(),
+                    task: work.task,
+                });
+                continue;
+            }
+
+            let mut attempts = Vec::<Outcome>::new();
+            let succeeded = loop {
+                let task_deadline = work.timeout.map(|v| Deadline::new(v, "task"));
+                let outcome = Self::run_work(
+                    &work,
+                    attempts.len() + 1,
+                    Deadline::earliest(node_deadline, task_deadline),
+                    ctx,
+                )?;
+                if outcome == Outcome::Success {
+                    break true;
+                }
+                attempts.push(outcome);
+
+                let retry = match &work.retry {
+                    | Some(v) => v,
+                    | None => break false,
+                };
+                let node_expired = node_deadline.is_some_and(|v| Instant::now() >= v.at);
+                if attempts.len() >= retry.attempts as usize || node_expired || !outcome.retryable(retry) {
+                    break false;
+                }
+                Self::sleep(retry.delay_before(attempts.len()), &ctx.supervisor)?;
+            };
+            if succeeded {
+                if let Err(e) = ctx.progress.lock().complete(item) {
+                    warnings.push(format!("failed to record progress: {}", e));
+                }
+                continue;
+            }
+
+            let failure = if work.retry.is_none() {
+                format!("command: {} {}", work.command, attempts[0])
+            } else {
+                format!(
+                    "command: {} failed after {} attempt(s)\n{}",
+                    work.command,
+                    attempts.len(),
+                    attempts
+                        .iter()
+                        .enumerate()
+                        .map(|(i, v)| format!("  attempt {}: {}", i + 1, v))
+                        .join("\n")
+                )
+            };
+            match &work.allow_failure {
+                | Some(v) if v.allows(attempts.last().unwrap().code()) => {
+                    allowed.push(format!("{} (failure allowed)", failure));
+                },
+                | _ => return Err(anyhow::anyhow!("{}", failure)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes a single attempt of a work item.
+    fn run_work(work: &Work, attempt: usize, deadline: Option<Deadline>, ctx: &Context) -> Result<Outcome> {
+        let output = &ctx.output;
+        let mut shell = work.shell.split_whitespace();
+        let mut cmd_proc = std::process::Command::new(shell.next().unwrap());
+        for v in shell {
+            cmd_proc.arg(v);
+        }
+        cmd_proc.envs(&work.env);
+        if let Some(w) = &work.workdir {
+            cmd_proc.current_dir(w);
+        }
+        cmd_proc.arg(&work.command);
+        cmd_proc.stdin(Stdio::null());
+        // every task gets its own process group so that it can be terminated
+        // together with all of its children
+        cmd_proc.process_group(0);
+
+        // reports need the output of the task even if it is not shown
+        let report = ctx.reports.is_some();
+        let piped = match output.style {
+            | OutputStyle::Inherit => report,
+            | OutputStyle::Prefix { .. } | OutputStyle::Grouped => true,
+        };
+        match (output.stdout || report, piped) {
+            | (false, _) => cmd_proc.stdout(Stdio::null()),
+            | (true, true) => cmd_proc.stdout(Stdio::piped()),
+            | (true, false) if output.redirect_stdout => {
+                cmd_proc.stdout(Stdio::from(std::io::stderr().as_fd().try_clone_to_owned()?))
+            },
+            | (true, false) => cmd_proc.stdout(Stdio::inherit()),
+        };
+        match (output.stderr || report, piped) {
+            | (false, _) => cmd_proc.stderr(Stdio::null()),
+            | (true, true) => cmd_proc.stderr(Stdio::piped()),
+            | (true, false) => cmd_proc.stderr(Stdio::inherit()),
+        };
+
+        let mut child = {
+            let mut sv = ctx.supervisor.lock();
+            if sv.cancelled {
+                return Err(Cancelled.into());
+            }
+            let child = cmd_proc.spawn()?;
+            sv.groups.insert(child.id(), work.label());
+            child
+        };
+        let pgid = child.id();
+        let started = Instant::now();
+        ctx.emit(EventKind::TaskStarted {
+            node: work.node.clone(),
+            cell: work.cell.clone(),
+            task: work.task,
+            attempt,
+            pid: pgid,
+        });
+
+        let mut readers = Vec::<std::thread::JoinHandle<()>>::new();
+        let exited = Arc::new(AtomicBool::new(false));
+        let abandoned = Arc::new(AtomicBool::new(false));
+        // STDOUT and STDERR in the order they were written (grouped output only)
+        let captured = Arc::new(Mutex::new(Captured::default()));
+        let tails = (
+            Arc::new(Mutex::new(Vec::<u8>::new())),
+            Arc::new(Mutex::new(Vec::<u8>::new())),
+        );
+        let forward = |shown: bool| {
+            match (shown, output.style) {
+                | (false, _) => Forward::Discard,
+                | (true, OutputStyle::Inherit) => Forward::Raw,
+                | (true, OutputStyle::Prefix { color }) => Forward::Prefix(Self::prefix(&work.node, &work.cell, color)),
+                | (true, OutputStyle::Grouped) => Forward::Capture(captured.clone()),
+            }
+        };
+        if let Some(v) = child.stdout.take() {
+            let forward = forward(output.stdout);
+            let tail = report.then(|| tails.0.clone());
+            let stream = if output.redirect_stdout {
+                Stream::Stderr
+            } else {
+                Stream::Stdout
+            };
+            let source = Pipe::new(v, &exited, &abandoned);
+            readers.push(std::thread::spawn(move || Self::pump(source, stream, forward, tail)));
+        }
+        if let Some(v) = child.stderr.take() {
+            let forward = forward(output.stderr);
+            let tail = report.then(|| tails.1.clone());
+            let source = Pipe::new(v, &exited, &abandoned);
+            readers.push(std::thread::spawn(move || {
+                Self::pump(source, Stream::Stderr, forward, tail)
+            }));
+        }
+
+        let status = Self::wait(child, deadline);
+        exited.store(true, Ordering::Release);
+        for reader in readers {
+            reader.join().expect("output reader panicked");
+        }
+        if abandoned.load(Ordering::Acquire) {
+            eprintln!(
+                "warning: {}: stopped reading the output of processes the task left running",
+                work.label()
+            );
+        }
+        let cancelled = {
+            let mut sv = ctx.supervisor.lock();
+            sv.groups.remove(&pgid);
+            sv.cancelled
+        };
+        let outcome: Result<Outcome> = match status? {
+            | None => Ok(Outcome::TimedOut(deadline.unwrap())),
+            | Some(status) => {
+                match (status.code(), status.signal()) {
+                    | (Some(0), _) => Ok(Outcome::Success),
+                    | (_, Some(_)) if cancelled => Err(Cancelled.into()),
+                    | (Some(v), _) => Ok(Outcome::Code(v)),
+                    | (None, v) => Ok(Outcome::Signal(v.unwrap_or_default())),
+                }
+            },
+        };
+
+        ctx.emit(EventKind::TaskFinished {
+            node: work.node.clone(),
+            cell: work.cell.clone(),
+            task: work.task,
+            attempt,
+            pid: pgid,
+            exit_code: match &outcome {
+                | Ok(v) => v.code(),
+                | Err(_) => None,
+            },
+            outcome: match &outcome {
+                | Ok(v) => v.to_string(),
+                | Err(e) => e.to_string(),
+            },
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+
+        if let Some(reports) = &ctx.reports {
+            let (failure, allowed) = match &outcome {
+                | Ok(Outcome::Success) => (None, false),
+                | Ok(v) => {
+                    (
+                        Some(v.to_string()),
+                        work.allow_failure.as_ref().is_some_and(|a| a.allows(v.code())),
+                    )
+                },
+                | Err(e) => (Some(e.to_string()), false),
+            };
+            reports.lock().push(TaskReport {
+                node: work.node.clone(),
+                cell: work.cell.clone(),
+                task: work.task,
+                attempt,
+                duration: started.elapsed(),
+                failure,
+                allowed,
+                stdout: std::mem::take(&mut *tails.0.lock()),
+                stderr: std::mem::take(&mut *tails.1.lock()),
+            });
+        }
+
+        // STDERR takes the block if STDOUT is not shown or redirected
+        let group = match (output.stdout, output.stderr) {
+            | (true, _) if !output.redirect_stdout => Some(Stream::Stdout),
+            | (true, _) | (false, true) => Some(Stream::Stderr),
+            | (false, false) => None,
+        };
+        if let (OutputStyle::Grouped, Some(stream)) = (output.style, group) {
+            let header = match &outcome {
+                | Ok(v) => {
+                    match v.code() {
+                        | Some(code) => format!("exit code {}", code),
+                        | None => v.to_string(),
+                    }
+                },
+                | Err(e) => e.to_string(),
+            };
+            let out = Self::format_group(&work.label(), &header, started.elapsed(), &captured.lock().data);
+            stream.write(&out);
+        }
+        outcome
+    }
+
+    /// Formats the captured output of a work item as one block with a header
+    /// that contains the label, the outcome and the duration (in milliseconds
+    /// precision) of the work item.
+    pub(crate) fn format_group(label: &str, outcome: &str, duration: Duration, captured: &[u8]) -> Vec<u8> {
+        let duration = Duration::from_millis(duration.as_millis() as u64);
+        let mut out = format!(
+            "--- {} | {} | {} ---\n",
+            label,
+            outcome,
+            humantime_serde::re::humantime::format_duration(duration)
+        )
+        .into_bytes();
+        out.extend_from_slice(captured);
+        if !captured.is_empty() && !captured.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+        out
+    }
+
+    /// The prefix for lines of output of a work item. Colors are derived from
+    /// the node and matrix cell so that they are stable across executions.
+    pub(crate) fn prefix(node: &str, cell: &[u8], color: bool) -> String {
+        let prefix = if cell.is_empty() {
+            format!("[{}]", node)
+        } else {
+            format!("[{} cell={}]", node, cell.iter().join(","))
+        };
+        if !color {
+            return prefix;
+        }
+
+        const PALETTE: [Color; 6] = [
+            Color::Cyan,
+            Color::Green,
+            Color::Yellow,
+            Color::Blue,
+            Color::Magenta,
+            Color::Red,
+        ];
+        let mut hasher = std::hash::DefaultHasher::new();
+        node.hash(&mut hasher);
+        cell.hash(&mut hasher);
+        let color = PALETTE[hasher.finish() as usize % PALETTE.len()];
+        prefix.with(color).to_string()
+    }
+
+    /// Prefixes a line of output. A line break is added if the line does not
+    /// end with one (i.e. the last line of the output).
+    pub(crate) fn prefix_line(prefix: &str, line: &[u8]) -> Vec<u8> {
+        let mut out = Vec::<u8>::with_capacity(prefix.len() + 2 + line.len());
+        out.extend_from_slice(prefix.as_bytes());
+        out.push(b' ');
+        out.extend_from_slice(line);
+        if !out.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+        out
+    }
+
+    /// Reads the next line from `source` into `line`, but not more than
+    /// `LINE_LIMIT` bytes so that output without line breaks does not pile up.
+    pub(crate) fn read_line<R: BufRead>(source: &mut R, line: &mut Vec<u8>) -> std::io::Result<usize> {
+        source.take(LINE_LIMIT as u64).read_until(b'\n', line)
+    }
+
+    /// Reads everything from `source`, forwards it as requested and keeps the
+    /// last `TAIL_LIMIT` bytes in `tail`. Prefixed output is forwarded line by
+    /// line (or in parts of `LINE_LIMIT` bytes) and flushed right away to keep
+    /// lines of concurrent tasks intact.
+    fn pump<R: Read>(source: R, stream: Stream, forward: Forward, tail: Option<Arc<Mutex<Vec<u8>>>>) {
+        let mut source = BufReader::new(source);
+        let mut chunk = Vec::<u8>::new();
+        loop {
+            chunk.clear();
+            let read = match forward {
+                | Forward::Prefix(_) => Self::read_line(&mut source, &mut chunk),
+                | _ => {
+                    source.fill_buf().map(|v| {
+                        chunk.extend_from_slice(v);
+                        v.len()
+                    })
+                },
+            };
+            match read {
+                | Ok(0) | Err(_) => return,
+                | Ok(n) => {
+                    if !matches!(forward, Forward::Prefix(_)) {
+                        source.consume(n);
+                    }
+                },
+            }
+            if let Some(tail) = &tail {
+                let mut tail = tail.lock();
+                tail.extend_from_slice(&chunk);
+                if tail.len() > TAIL_LIMIT {
+                    let excess = tail.len() - TAIL_LIMIT;
+                    tail.drain(..excess);
+                }
+            }
+            match &forward {
+                | Forward::Discard => {},
+                | Forward::Raw => stream.write(&chunk),
+                | Forward::Prefix(prefix) => stream.write(&Self::prefix_line(prefix, &chunk)),
+                | Forward::Capture(buffer) => buffer.lock().append(stream, &chunk),
+            }
+        }
+    }
+
+    /// Sleeps for the given duration unless the run is cancelled in the
+    /// meantime.
+    fn sleep(duration: Duration, supervisor: &Mutex<Supervisor>) -> Result<()> {
+        let until = Instant::now() + duration;
+        loop {
+            if supervisor.lock().cancelled {
+                return Err(Cancelled.into());
+            }
+            let now = Instant::now();
+            if now >= until {
+                return Ok(());
+            }
+            std::thread::sleep((until - now).min(Duration::from_millis(50)));
+        }
+    }
+
+    /// Waits for the child to exit. If the deadline is reached first, the
+    /// process group of the child is killed and `None` is returned.
+    fn wait(mut child: std::process::Child, deadline: Option<Deadline>) -> Result<Option<ExitStatus>> {
+        let deadline = match deadline {
+            | Some(v) => v,
+            | None => return Ok(Some(child.wait()?)),
+        };
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(Some(status));
+            }
+            if Instant::now() >= deadline.at {
+                unsafe {
+                    libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+                }
+                child.wait()?;
+                return Ok(None);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
 }
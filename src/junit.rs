@@ -0,0 +1,122 @@
+use {
+    itertools::Itertools,
+    std::{
+        collections::BTreeMap,
+        time::Duration,
+    },
+};
+
+/// The outcome of the last attempt of a task for one matrix entry.
+#[derive(Debug, Clone)]
+pub(crate) struct TaskReport {
+    pub node: String,
+    pub cell: Vec<u8>,
+    pub task: usize,
+    pub attempt: usize,
+    pub duration: Duration,
+    /// The failure message, `None` if the task succeeded.
+    pub failure: Option<String>,
+    /// Whether the failure is allowed (and thereby not reported as such).
+    pub allowed: bool,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+fn escape(v: &str) -> String {
+    let mut out = String::with_capacity(v.len());
+    for c in v.chars() {
+        match c {
+            | '&' => out.push_str("&amp;"),
+            | '<' => out.push_str("&lt;"),
+            | '>' => out.push_str("&gt;"),
+            | '"' => out.push_str("&quot;"),
+            | '\'' => out.push_str("&apos;"),
+            // characters that are not allowed in XML 1.0
+            | c if (c as u32) < 0x20 && c != '\t' && c != '\n' && c != '\r' => {},
+            | c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Renders the reports as JUnit XML with one testsuite per node and one
+/// testcase per (matrix entry, task). Only the last attempt of every testcase
+/// is taken into account.
+pub(crate) fn render(reports: &[TaskReport]) -> String {
+    let mut suites = BTreeMap::<&str, BTreeMap<(&[u8], usize), &TaskReport>>::new();
+    for r in reports {
+        let cases = suites.entry(&r.node).or_default();
+        let key = (r.cell.as_slice(), r.task);
+        if cases.get(&key).is_none_or(|v| v.attempt < r.attempt) {
+            cases.insert(key, r);
+        }
+    }
+
+    let failed = |r: &TaskReport| r.failure.is_some() && !r.allowed;
+    let seconds = |v: Duration| format!("{:.3}", v.as_secs_f64());
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let all = suites.values().flat_map(|v| v.values()).collect_vec();
+    out.push_str(&format!(
+        "<testsuites name=\"neomake\" tests=\"{}\" failures=\"{}\" time=\"{}\">\n",
+        all.len(),
+        all.iter().filter(|r| failed(r)).count(),
+        seconds(all.iter().map(|r| r.duration).sum())
+    ));
+    for (node, cases) in &suites {
+        out.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{}\">\n",
+            escape(node),
+            cases.len(),
+            cases.values().filter(|r| failed(r)).count(),
+            seconds(cases.values().map(|r| r.duration).sum())
+        ));
+        for r in cases.values() {
+            let name = if r.cell.is_empty() {
+                format!("task={}", r.task)
+            } else {
+                format!("cell={} task={}", r.cell.iter().join(","), r.task)
+            };
+            out.push_str(&format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{}\">\n",
+                escape(&name),
+                escape(node),
+                seconds(r.duration)
+            ));
+            if let Some(failure) = &r.failure {
+                let message = if r.attempt > 1 {
+                    format!("{} (attempt {})", failure, r.attempt)
+                } else {
+                    failure.clone()
+                };
+                if r.allowed {
+                    out.push_str(&format!(
+                        "      <properties><property name=\"allowed_failure\" value=\"{}\"/></properties>\n",
+                        escape(&message)
+                    ));
+                } else {
+                    out.push_str(&format!(
+                        "      <failure message=\"{}\" type=\"failure\"/>\n",
+                        escape(&message)
+                    ));
+                }
+            }
+            if !r.stdout.is_empty() {
+                out.push_str(&format!(
+                    "      <system-out>{}</system-out>\n",
+                    escape(&String::from_utf8_lossy(&r.stdout))
+                ));
+            }
+            if !r.stderr.is_empty() {
+                out.push_str(&format!(
+                    "      <system-err>{}</system-err>\n",
+                    escape(&String::from_utf8_lossy(&r.stderr))
+                ));
+            }
+            out.push_str("    </testcase>\n");
+        }
+        out.push_str("  </testsuite>\n");
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
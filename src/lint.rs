@@ -0,0 +1,465 @@
+use {
+    crate::{
+        compiler::Compiler,
+        workflow::{
+            ArgumentType,
+            Env,
+            NodeSelector,
+            Retry,
+            Workflow,
+        },
+    },
+    itertools::Itertools,
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        path::{
+            Path,
+            PathBuf,
+        },
+    },
+    yaml_rust2::{
+        parser::{
+            MarkedEventReceiver,
+            Parser,
+        },
+        scanner::Marker,
+        Event,
+    },
+};
+
+/// A problem found in a workflow.
+#[derive(Debug)]
+pub(crate) struct Problem {
+    /// The included workflow file that contains the problem, `None` for the
+    /// linted workflow itself.
+    pub file: Option<String>,
+    /// Path of the offending value (e.g. `nodes.a.pre[0].name`).
+    pub path: String,
+    /// Line and column (both starting at 1) of the offending value.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((line, col)) = self.position {
+            write!(f, "{}:{}: ", line, col)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+enum Frame {
+    /// Contains the key of the value that is parsed next, `None` while parsing
+    /// a key.
+    Mapping(Option<String>),
+    /// Contains the index of the item that is parsed next.
+    Sequence(usize),
+}
+
+/// Records the position of every value in a YAML document by its path.
+#[derive(Default)]
+struct Positions {
+    stack: Vec<Frame>,
+    positions: HashMap<String, Marker>,
+}
+
+impl Positions {
+    fn path(&self) -> String {
+        let mut path = String::new();
+        for frame in &self.stack {
+            match frame {
+                | Frame::Mapping(Some(key)) => {
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    path.push_str(key);
+                },
+                | Frame::Mapping(None) => {},
+                | Frame::Sequence(idx) => path.push_str(&format!("[{}]", idx)),
+            }
+        }
+        path
+    }
+
+    fn record(&mut self, mark: Marker) {
+        // keys are recorded before their values which is the preferred position
+        self.positions.entry(self.path()).or_insert(mark);
+    }
+
+    /// Moves on to the next entry of the current collection.
+    fn advance(&mut self) {
+        match self.stack.last_mut() {
+            | Some(Frame::Mapping(key)) => *key = None,
+            | Some(Frame::Sequence(idx)) => *idx += 1,
+            | None => {},
+        }
+    }
+
+    /// Finds the position of the path or its closest recorded parent.
+    fn find(&self, path: &str) -> Option<(usize, usize)> {
+        let mut path = path;
+        loop {
+            if let Some(mark) = self.positions.get(path) {
+                return Some((mark.line(), mark.col() + 1));
+            }
+            path = &path[..path.rfind(['.', '['])?];
+        }
+    }
+}
+
+impl MarkedEventReceiver for Positions {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        let parsing_key = matches!(self.stack.last(), Some(Frame::Mapping(None)));
+        match ev {
+            | Event::Scalar(v, ..) if parsing_key => {
+                if let Some(Frame::Mapping(key)) = self.stack.last_mut() {
+                    *key = Some(v);
+                }
+                self.record(mark);
+            },
+            | Event::Scalar(..) | Event::Alias(_) => {
+                self.record(mark);
+                self.advance();
+            },
+            | Event::MappingStart(..) => {
+                self.record(mark);
+                self.stack.push(Frame::Mapping(None));
+            },
+            | Event::SequenceStart(..) => {
+                self.record(mark);
+                self.stack.push(Frame::Sequence(0));
+            },
+            | Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+                self.advance();
+            },
+            | _ => {},
+        }
+    }
+}
+
+/// Validates the workflow without planning or executing anything and returns
+/// all problems found, ordered by their position. Includes are resolved
+/// relative to `file` and linted as well, their problems follow the ones of
+/// the workflow itself.
+pub(crate) fn lint(data: &str, file: &Path) -> Vec<Problem> {
+    lint_file(data, file, None, &mut vec![]).0
+}
+
+/// Lints a workflow and the files it includes. For included files, `external`
+/// contains the nodes of the composed root workflow that their prerequisites
+/// can reference. Recursions in the graph are only checked for the root
+/// workflow since they can span several files. Also returns whether any
+/// prerequisite does not resolve.
+fn lint_file(
+    data: &str,
+    file: &Path,
+    external: Option<&HashSet<String>>,
+    stack: &mut Vec<PathBuf>,
+) -> (Vec<Problem>, bool) {
+    let origin = external.map(|_| file.display().to_string());
+    let mut positions = Positions::default();
+    if let Err(e) = Parser::new_from_str(data).load(&mut positions, false) {
+        let problem = Problem {
+            file: origin,
+            path: "".to_owned(),
+            position: Some((e.marker().line(), e.marker().col() + 1)),
+            message: e.info().to_owned(),
+        };
+        return (vec![problem], true);
+    }
+
+    let mut problems = Vec::<(String, String)>::new();
+    let mut report = |path: String, message: String| problems.push((path, message));
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Versioned {
+        version: String,
+    }
+    match serde_yaml::from_str::<Versioned>(data) {
+        | Ok(v) => {
+            if let Err(e) = Workflow::check_version(&v.version) {
+                report("version".to_owned(), e.to_string());
+            }
+        },
+        | Err(e) => report("version".to_owned(), e.to_string()),
+    }
+
+    let wf = match serde_yaml::from_str::<Workflow>(data) {
+        | Ok(v) => v,
+        | Err(e) => {
+            // the structure is invalid, nothing else can be checked
+            let position = e.location().map(|l| (l.line(), l.column()));
+            let message = e.to_string();
+            let (path, message) = match message.split_once(": ") {
+                | Some((path, msg)) if !path.contains(' ') => (path.to_owned(), msg.to_owned()),
+                | _ => ("".to_owned(), message),
+            };
+            let problem = Problem {
+                file: origin,
+                path,
+                position,
+                message: message
+                    .rsplit_once(" at line ")
+                    .map(|v| v.0.to_owned())
+                    .unwrap_or(message),
+            };
+            return (vec![problem], true);
+        },
+    };
+
+    let check_env = |path: String, env: &Option<Env>, report: &mut dyn FnMut(String, String)| {
+        if let Some(capture) = env.as_ref().and_then(|v| v.capture.as_ref()) {
+            if let Err(e) = fancy_regex::Regex::new(capture) {
+                report(format!("{}.capture", path), format!("invalid regex: {}", e));
+            }
+        }
+    };
+
+    let check_retry = |path: String, retry: &Option<Retry>, report: &mut dyn FnMut(String, String)| {
+        if let Some(Err(e)) = retry.as_ref().map(|v| v.validate()) {
+            report(path, format!("invalid retry policy: {}", e));
+        }
+    };
+
+    check_env("env".to_owned(), &wf.env, &mut report);
+
+    let mut declared = HashSet::<&str>::new();
+    for (idx, arg) in wf.args.iter().flatten().enumerate() {
+        let path = format!("args[{}]", idx);
+        if !declared.insert(&arg.name) {
+            report(format!("{}.name", path), format!("duplicate argument: {}", arg.name));
+        }
+        if arg.kind == ArgumentType::Enum && arg.values.as_ref().is_none_or(|v| v.is_empty()) {
+            report(path.clone(), "enum argument without values".to_owned());
+        }
+        if let Some(default) = arg.default_value() {
+            if let Err(e) = arg.coerce(&default) {
+                report(format!("{}.default", path), e.to_string());
+            }
+        }
+    }
+
+    let templates = wf.templates.clone().unwrap_or_default();
+    // templates are checked like nodes
+    let entries = wf
+        .nodes
+        .iter()
+        .map(|(name, node)| ("nodes", name, node))
+        .chain(templates.iter().map(|(name, node)| ("templates", name, node)))
+        .sorted_by_key(|v| (v.0, v.1))
+        .collect_vec();
+    let mut dangling = false;
+    for &(section, name, node) in &entries {
+        if let Some(extends) = &node.extends {
+            if !templates.contains_key(extends.template()) {
+                dangling = true;
+                report(
+                    format!("{}.{}.extends", section, name),
+                    format!("template not found: {}", extends.template()),
+                );
+            }
+        }
+    }
+
+    // prerequisites may reference included nodes
+    let mut composed = wf.clone();
+    let mut expanded = false;
+    if !dangling {
+        match composed.resolve_templates() {
+            | Ok(_) => expanded = true,
+            | Err(e) => {
+                dangling = true;
+                report("templates".to_owned(), e.to_string());
+            },
+        }
+    }
+    let resolved = composed.resolve_includes(file, &mut vec![]);
+
+    let root = composed.nodes.keys().cloned().collect::<HashSet<_>>();
+    let mut included = Vec::<Problem>::new();
+    let mut unresolved = false;
+    stack.push(file.canonicalize().unwrap_or_else(|_| file.to_path_buf()));
+    let base = file.parent().unwrap_or(Path::new(""));
+    for (idx, include) in wf.include.iter().flatten().enumerate() {
+        let path = format!("include[{}].path", idx);
+        let files = match include.files(base) {
+            | Ok(v) => v,
+            | Err(e) => {
+                unresolved = true;
+                report(path, e.to_string());
+                continue;
+            },
+        };
+        for included_file in files {
+            // recursive includes are reported when resolving them
+            if stack.contains(&included_file.canonicalize().unwrap_or_else(|_| included_file.clone())) {
+                continue;
+            }
+            match std::fs::read_to_string(&included_file) {
+                | Ok(data) => {
+                    let (problems, unresolved) =
+                        lint_file(&data, &included_file, Some(external.unwrap_or(&root)), stack);
+                    included.extend(problems);
+                    dangling |= unresolved;
+                },
+                | Err(e) => {
+                    unresolved = true;
+                    report(
+                        path.clone(),
+                        format!("failed to read {}: {}", included_file.display(), e),
+                    );
+                },
+            }
+        }
+    }
+    stack.pop();
+    // problems of included files are reported where they are
+    if let Err(e) = resolved {
+        if included.is_empty() && !unresolved {
+            report("include".to_owned(), e.to_string());
+        }
+    }
+
+    for &(section, name, node) in &entries {
+        let path = format!("{}.{}", section, name);
+        if section == "nodes" {
+            if let Err(e) = Workflow::check_node_name(name) {
+                report(path.clone(), e.to_string());
+            }
+        }
+
+        for (idx, sel) in node.pre.iter().flatten().enumerate() {
+            match sel {
+                | NodeSelector::Name(v) => {
+                    if !composed.nodes.contains_key(v) && !external.is_some_and(|e| e.contains(v)) {
+                        dangling = true;
+                        report(format!("{}.pre[{}].name", path, idx), format!("node not found: {}", v));
+                    }
+                },
+                | NodeSelector::Regex(v) => {
+                    let path = format!("{}.pre[{}].regex", path, idx);
+                    match fancy_regex::Regex::new(v) {
+                        | Ok(regex) => {
+                            if !composed.nodes.keys().any(|n| regex.is_match(n).unwrap_or_default()) {
+                                report(path, format!("regex matches no node: {}", v));
+                            }
+                        },
+                        | Err(e) => {
+                            dangling = true;
+                            report(path, format!("invalid regex: {}", e));
+                        },
+                    }
+                },
+            }
+        }
+
+        check_env(format!("{}.env", path), &node.env, &mut report);
+        check_retry(format!("{}.retry", path), &node.retry, &mut report);
+        if let Some(matrix) = &node.matrix {
+            let cells = matrix
+                .dimensions
+                .iter()
+                .enumerate()
+                .flat_map(|(x, dim)| {
+                    dim.iter()
+                        .enumerate()
+                        .map(move |(y, cell)| (format!("dimensions[{}][{}]", x, y), cell))
+                })
+                .chain(
+                    matrix
+                        .include
+                        .iter()
+                        .flatten()
+                        .enumerate()
+                        .map(|(idx, cell)| (format!("include[{}]", idx), cell)),
+                );
+            for (idx, rule) in matrix.exclude.iter().flatten().enumerate() {
+                if rule.is_empty() {
+                    report(
+                        format!("{}.matrix.exclude[{}]", path, idx),
+                        "empty rule excludes every entry".to_owned(),
+                    );
+                }
+            }
+            let first = matrix.dimensions.first().map(|v| v.len()).unwrap_or_default();
+            if first + matrix.include.as_ref().map(|v| v.len()).unwrap_or_default() > u8::MAX as usize + 1 {
+                report(
+                    format!("{}.matrix.include", path),
+                    "too many matrix entries in the first dimension".to_owned(),
+                );
+            }
+            for (cell_path, cell) in cells {
+                let path = format!("{}.matrix.{}", path, cell_path);
+                check_env(format!("{}.env", path), &cell.env, &mut report);
+                if cell.values.as_ref().is_some_and(|v| v.contains_key("cell")) {
+                    report(
+                        format!("{}.values.cell", path),
+                        "reserved for the coordinates of the matrix entry".to_owned(),
+                    );
+                }
+            }
+        }
+
+        // templates might not define the matrix their tasks are rendered with
+        if let Some(node) = composed.nodes.get(name).filter(|_| section == "nodes" && expanded) {
+            let values = node
+                .matrix
+                .iter()
+                .flat_map(|v| v.dimensions.iter().flatten().chain(v.include.iter().flatten()))
+                .flat_map(|v| v.values.iter().flatten().map(|v| v.0.as_str()))
+                .collect::<HashSet<_>>();
+            for placeholder in Compiler::placeholders(node).iter().sorted() {
+                if let Some(key) = placeholder.strip_prefix("matrix.") {
+                    let key = key.split('.').next().unwrap_or_default();
+                    if key != "cell" && !values.contains(key) {
+                        report(format!("{}.tasks", path), format!("unknown matrix value: {}", key));
+                    }
+                }
+            }
+        }
+
+        for (idx, task) in node.tasks.iter().enumerate() {
+            let path = format!("{}.tasks[{}]", path, idx);
+            if let Err(e) = handlebars::Template::compile(&task.script) {
+                let message = match e.pos() {
+                    | Some((line, col)) => format!("invalid template (line {}, column {}): {}", line, col, e.reason()),
+                    | None => format!("invalid template: {}", e.reason()),
+                };
+                report(format!("{}.script", path), message);
+            }
+            check_env(format!("{}.env", path), &task.env, &mut report);
+            check_retry(format!("{}.retry", path), &task.retry, &mut report);
+        }
+    }
+
+    // recursions can only be determined if all prerequisites resolve
+    if !dangling && external.is_none() {
+        let all = composed.nodes.keys().cloned().collect::<HashSet<_>>();
+        if let Err(e) = Compiler::new(composed).determine_order(&all) {
+            report("nodes".to_owned(), e.to_string());
+        }
+    }
+
+    let problems = problems
+        .into_iter()
+        .map(|(path, message)| {
+            Problem {
+                file: origin.clone(),
+                position: positions.find(&path),
+                path,
+                message,
+            }
+        })
+        .sorted_by_key(|v| v.position)
+        .chain(included)
+        .collect();
+    (problems, dangling)
+}
@@ -9,18 +9,24 @@ use {
 };
 
 pub mod args;
+pub mod cache;
 pub mod compiler;
+pub mod events;
 pub mod exec;
+pub mod junit;
+pub mod lint;
 pub mod plan;
 pub mod reference;
+pub mod state;
 pub mod workflow;
 
 use {
-    crate::{compiler::Compiler, workflow::Workflow},
+    crate::{cache::Cache, compiler::Compiler, workflow::Workflow},
     anyhow::Result,
-    args::{ManualFormat, WorkflowInitOutput},
-    exec::{ExecutionEngine, OutputMode},
-    std::path::PathBuf,
+    args::{ExecutionArgs, ManualFormat, Nodes, WorkflowInitOutput},
+    events::EventWriter,
+    exec::{ExecutionEngine, FailureMode, OutputMode},
+    std::{collections::HashMap, path::PathBuf},
 };
 
 #[tokio::main]
@@ -41,10 +47,14 @@ async fn main() -> Result<()> {
             }
             Ok(())
         },
-        | crate::args::Command::Autocomplete { path, shell } => {
+        | crate::args::Command::Autocomplete { path, shell, workflow } => {
+            let args = match workflow {
+                | Some(v) => Workflow::load(v)?.args.unwrap_or_default(),
+                | None => vec![],
+            };
             let out_path = PathBuf::from(path);
             std::fs::create_dir_all(&out_path)?;
-            reference::build_shell_completion(&out_path, &shell)?;
+            reference::build_shell_completion(&out_path, &shell, &args)?;
             Ok(())
         },
         | crate::args::Command::Workflow(wf) => match wf {
@@ -55,6 +65,16 @@ async fn main() -> Result<()> {
                 );
                 Ok(())
             },
+            | crate::args::WorkflowCommand::Lint { workflow } => {
+                let problems = lint::lint(&std::fs::read_to_string(&workflow)?, Path::new(&workflow));
+                for p in &problems {
+                    println!("{}:{}", p.file.as_deref().unwrap_or(&workflow), p);
+                }
+                if !problems.is_empty() {
+                    return Err(anyhow::anyhow!("found {} problem(s) in {}", problems.len(), workflow));
+                }
+                Ok(())
+            },
             | crate::args::WorkflowCommand::Init { template, output } => {
                 match output {
                     | WorkflowInitOutput::File(f) => std::fs::write(f, template.render())?,
@@ -63,19 +83,57 @@ async fn main() -> Result<()> {
                 Ok(())
             },
         },
-        | crate::args::Command::Execute {
-            plan,
-            workers,
-            no_stdout,
-            no_stderr,
-        } => {
-            let exec_engine = ExecutionEngine::new(OutputMode {
-                stdout: !no_stdout,
-                stderr: !no_stderr,
-            });
-            exec_engine.execute(&plan, workers)?;
+        | crate::args::Command::Events(ev) => match ev {
+            | crate::args::EventsCommand::Schema => {
+                print!(
+                    "{}",
+                    serde_json::to_string_pretty(&schemars::schema_for!(crate::events::Event)).unwrap()
+                );
+                Ok(())
+            },
+        },
+        | crate::args::Command::Cache { state_dir, command } => {
+            let cache = Cache::new(Path::new(&state_dir));
+            match command {
+                | crate::args::CacheCommand::List => {
+                    let (entries, corrupt) = cache.entries()?;
+                    for entry in corrupt {
+                        eprintln!(
+                            "warning: corrupt cache entry {}: {}",
+                            entry.path.display(),
+                            entry.reason
+                        );
+                    }
+                    // fingerprints of valid entries are SHA-256 digests
+                    for entry in entries {
+                        println!(
+                            "{}  {}  {}  {} file(s)  {} byte(s)",
+                            &entry.fingerprint[..12],
+                            entry.created.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                            entry.node,
+                            entry.files.len(),
+                            entry.files.iter().map(|f| f.size).sum::<u64>()
+                        );
+                    }
+                },
+                | crate::args::CacheCommand::Prune { older_than } => {
+                    let (entries, blobs) = cache.prune(older_than)?;
+                    println!("removed {} entries and {} files", entries, blobs);
+                },
+                | crate::args::CacheCommand::Clear => cache.clear()?,
+            }
             Ok(())
         },
+        | crate::args::Command::Execute { plan, execution } => {
+            execute(&plan, &execution)?;
+            Ok(())
+        },
+        | crate::args::Command::Run {
+            workflow,
+            nodes,
+            args,
+            execution,
+        } => run(&workflow, nodes, &args, &execution),
         | crate::args::Command::Plan {
             workflow,
             nodes,
@@ -106,6 +164,38 @@ async fn main() -> Result<()> {
             c.describe(&nodes, &format).await?;
             Ok(())
         },
+        | crate::args::Command::Dependents {
+            workflow,
+            nodes,
+            format,
+        } => {
+            let w = Workflow::load(&workflow)?;
+            let c = Compiler::new(w);
+            c.dependents(&nodes, &format).await?;
+            Ok(())
+        },
+        | crate::args::Command::Explain {
+            workflow,
+            targets,
+            node,
+        } => {
+            let w = Workflow::load(&workflow)?;
+            let c = Compiler::new(w);
+            c.explain(&targets, &node).await?;
+            Ok(())
+        },
+        | crate::args::Command::Graph {
+            workflow,
+            nodes,
+            format,
+            details,
+        } => {
+            let w = Workflow::load(&workflow)?;
+            let nodes = nodes.select(&w)?;
+            let c = Compiler::new(w);
+            c.graph(&nodes, &format, details).await?;
+            Ok(())
+        },
         | crate::args::Command::Multiplex {
             commands,
             program,
@@ -138,7 +228,7 @@ async fn main() -> Result<()> {
             tokio::spawn(async move {
                 let program = program.clone();
                 let commands = commands.clone();
-                while let Some(_) = rx.recv().await {
+                while rx.recv().await.is_some() {
                     Multiplexer::new(program.clone(), stderr, commands.clone(), parallelism.unwrap_or(1))
                         .run()
                         .await
@@ -231,9 +321,63 @@ async fn main() -> Result<()> {
     }
 }
 
+fn run(workflow: &str, nodes: Nodes, args: &HashMap<String, String>, execution: &ExecutionArgs) -> Result<()> {
+    let w = Workflow::load(workflow)?;
+    let nodes = nodes.select(&w)?;
+    let x = Compiler::new(w).plan(&nodes, args)?;
+    execute(&x, execution)
+}
+
+fn execute(plan: &plan::ExecutionPlan, execution: &ExecutionArgs) -> Result<()> {
+    let mut exec_engine = ExecutionEngine::new(
+        OutputMode {
+            stdout: !execution.no_stdout,
+            stderr: !execution.no_stderr,
+            style: execution.output,
+            redirect_stdout: execution.events.as_deref() == Some("-"),
+        },
+        if execution.keep_going {
+            FailureMode::KeepGoing
+        } else {
+            FailureMode::FailFast
+        },
+    );
+    if execution.dry_run {
+        print!("{}", exec_engine.dry_run(plan)?);
+        return Ok(());
+    }
+    if let Some(events) = &execution.events {
+        // the output of the tasks is written to STDERR in this case
+        let sink: Box<dyn std::io::Write+Send> = match events.as_str() {
+            | "-" => Box::new(std::io::stdout()),
+            | path => Box::new(std::fs::File::create(path)?),
+        };
+        exec_engine = exec_engine.with_events(EventWriter::new(sink));
+    }
+    exec_engine = exec_engine
+        .with_resume(execution.resume)
+        .with_state_dir(Path::new(&execution.state_dir));
+    if let Some(junit) = &execution.junit {
+        exec_engine = exec_engine.with_junit(junit);
+    }
+    exec_engine.execute(plan, execution.workers)
+}
+
 #[cfg(test)]
 pub mod test {
-    use {crate::Workflow, anyhow::Result};
+    use {
+        crate::{
+            exec::{
+                ExecutionEngine,
+                FailureMode,
+                OutputMode,
+                OutputStyle,
+            },
+            Workflow,
+        },
+        anyhow::Result,
+        std::path::Path,
+    };
 
     const WF_MIN_YAML: &str = include_str!("../res/templates/min.neomake.yaml");
     const WF_MAX_YAML: &str = include_str!("../res/templates/max.neomake.yaml");
@@ -242,6 +386,70 @@ pub mod test {
     const WF_MY_YAML: &str = include_str!("../neomake.yaml");
     const ALL_WF_YAMLS: &[&str] = &[WF_MIN_YAML, WF_MAX_YAML, WF_PYTHON_YAML, WF_TEST_YAML, WF_MY_YAML];
 
+    /// Returns the command that executes the given test in a separate process
+    /// or `None` if this already is that process. Tests that depend on
+    /// state of the whole process (e.g. its STDOUT or signals) are executed
+    /// this way.
+    fn isolated(test: &str) -> Result<Option<std::process::Command>> {
+        if std::env::var("NEOMAKE_TEST_ISOLATED").is_ok_and(|v| v == test) {
+            return Ok(None);
+        }
+        let mut cmd = std::process::Command::new(std::env::current_exe()?);
+        cmd.args([test, "--exact", "--nocapture"])
+            .env("NEOMAKE_TEST_ISOLATED", test);
+        Ok(Some(cmd))
+    }
+
+    /// A directory for the files and state of a test. It is created empty and
+    /// removed once the test finishes, also if it fails.
+    struct TestDir(std::path::PathBuf);
+
+    impl TestDir {
+        fn new(test: &str) -> Result<Self> {
+            let path = std::env::temp_dir().join(format!("neomake-test-{}-{}", test, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path)?;
+            Ok(Self(path))
+        }
+    }
+
+    impl std::ops::Deref for TestDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Writes the selected streams of the tasks in the given style.
+    fn output(stdout: bool, stderr: bool, style: OutputStyle) -> OutputMode {
+        OutputMode {
+            stdout,
+            stderr,
+            style,
+            redirect_stdout: false,
+        }
+    }
+
+    /// An engine that keeps all state in `dir`.
+    fn engine(output: OutputMode, failure: FailureMode, dir: &Path) -> ExecutionEngine {
+        ExecutionEngine::new(output, failure).with_state_dir(dir)
+    }
+
+    /// Plans the given nodes of the workflow and executes them without showing
+    /// the output of the tasks. All state is kept in `dir`.
+    fn execute(yaml: &str, nodes: &[&str], failure: FailureMode, dir: &Path) -> Result<()> {
+        let plan = crate::compiler::Compiler::new(serde_yaml::from_str::<Workflow>(yaml)?)
+            .plan(&nodes.iter().map(|v| v.to_string()).collect(), &Default::default())?;
+        engine(output(false, false, OutputStyle::Inherit), failure, dir).execute(&plan, 4)
+    }
+
     #[tokio::test]
     pub async fn test_parse_workflow() -> Result<()> {
         for wf in ALL_WF_YAMLS {
@@ -249,4 +457,1455 @@ pub mod test {
         }
         Ok(())
     }
+
+    #[tokio::test]
+    pub async fn test_retry_backoff() -> Result<()> {
+        let retry = serde_yaml::from_str::<crate::workflow::Retry>("{ attempts: 4, delay: 1s, backoff: 2 }")?;
+        assert_eq!(retry.delay_before(1), std::time::Duration::from_secs(1));
+        assert_eq!(retry.delay_before(2), std::time::Duration::from_secs(2));
+        assert_eq!(retry.delay_before(3), std::time::Duration::from_secs(4));
+        assert_eq!(retry.delay_before(1000), crate::workflow::Retry::MAX_DELAY);
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_retry_validation() -> Result<()> {
+        for invalid in [
+            "{ attempts: 0 }",
+            "{ attempts: 2, backoff: -1 }",
+            "{ attempts: 2, backoff: .nan }",
+            "{ attempts: 2, backoff: .inf }",
+        ] {
+            let retry = serde_yaml::from_str::<crate::workflow::Retry>(invalid)?;
+            assert!(retry.validate().is_err(), "{}", invalid);
+            // invalid policies never panic
+            let _ = retry.delay_before(3);
+        }
+        let retry = serde_yaml::from_str::<crate::workflow::Retry>("{ attempts: 2, delay: 1s, backoff: 0 }")?;
+        retry.validate()?;
+        assert_eq!(retry.delay_before(2), std::time::Duration::ZERO);
+
+        let problems = crate::lint::lint(
+            r#"version: "0.0"
+nodes:
+  a:
+    retry: { attempts: 0 }
+    tasks:
+      - script: echo
+        retry: { attempts: 2, backoff: -1 }
+"#,
+            Path::new("neomake.yaml"),
+        )
+        .iter()
+        .map(|p| p.path.clone())
+        .collect::<Vec<_>>();
+        assert_eq!(problems, vec!["nodes.a.retry", "nodes.a.tasks[0].retry"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_lint_workflow() -> Result<()> {
+        for wf in ALL_WF_YAMLS {
+            assert!(crate::lint::lint(wf, Path::new("neomake.yaml")).is_empty());
+        }
+        let problems = crate::lint::lint(
+            r#"version: "0.0"
+nodes:
+  a:
+    pre:
+      - name: missing
+    tasks:
+      - script: echo {{#if x}}
+"#,
+            Path::new("neomake.yaml"),
+        )
+        .iter()
+        .map(|p| (p.path.clone(), p.position))
+        .collect::<Vec<_>>();
+        assert_eq!(problems, vec![
+            ("nodes.a.pre[0].name".to_owned(), Some((5, 9))),
+            ("nodes.a.tasks[0].script".to_owned(), Some((7, 9))),
+        ]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_cycle_diagnostics() -> Result<()> {
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a: { pre: [{ name: b }], tasks: [] }
+  b: { pre: [{ regex: "^c$" }], tasks: [] }
+  c: { pre: [{ name: a }], tasks: [] }
+  d: { pre: [{ regex: "^d" }], tasks: [] }
+"#,
+        )?;
+        let c = crate::compiler::Compiler::new(wf);
+        let err = c.plan(&["a".to_owned()].into(), &Default::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "found recursion in dag: a -> b -> c -> a\n  a -> b: pre name\n  b -> c: pre regex \"^c$\"\n  c -> a: pre \
+             name"
+        );
+        let err = c.plan(&["d".to_owned()].into(), &Default::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "found recursion in dag: d -> d\n  d -> d: pre regex \"^d\" matches the node itself"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_include_workflows() -> Result<()> {
+        let wf = Workflow::load("test/include/neomake.yaml")?;
+        assert_eq!(
+            wf.nodes
+                .keys()
+                .map(|v| v.as_str())
+                .collect::<std::collections::BTreeSet<_>>(),
+            ["all", "svc-a:build", "svc-a:setup", "svc-b:build"].into()
+        );
+        assert_eq!(wf.nodes["svc-a:build"].workdir.as_deref(), Some("test/include/svc-a"));
+        assert_eq!(
+            wf.nodes["svc-b:build"]
+                .env
+                .as_ref()
+                .and_then(|v| v.vars.as_ref())
+                .unwrap()["SERVICE"],
+            "b"
+        );
+        let c = crate::compiler::Compiler::new(wf);
+        let stages = c.determine_order(&["all".to_owned()].into())?;
+        assert_eq!(stages, vec![
+            ["svc-a:setup".to_owned()].into(),
+            ["svc-a:build".to_owned()].into(),
+            ["svc-b:build".to_owned()].into(),
+            ["all".to_owned()].into(),
+        ]);
+        // regex selectors stay regex selectors
+        let lines = c
+            .determine_chains("svc-a:build", "svc-a:setup")?
+            .unwrap()
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec!["  -> svc-a:setup (pre regex \"^(?:svc-a:setup)$\")"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_node_templates() -> Result<()> {
+        let mut wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+templates:
+  base:
+    shell: sh -c
+    env: { vars: { A: base, B: base } }
+    matrix: { parallel: false, dimensions: [[{}, {}]] }
+    tasks: [{ script: base }]
+  rust:
+    extends: base
+    env: { vars: { B: rust } }
+    tasks: [{ script: rust }]
+nodes:
+  build:
+    extends: rust
+    workdir: app
+    matrix: { parallel: true, dimensions: [[{}, {}, {}]] }
+    tasks: [{ script: build }]
+  test:
+    extends: { template: base, tasks: append }
+    shell: bash -c
+    tasks: [{ script: test }]
+"#,
+        )?;
+        wf.resolve_templates()?;
+        let build = &wf.nodes["build"];
+        assert_eq!(build.tasks.iter().map(|v| v.script.as_str()).collect::<Vec<_>>(), [
+            "base", "rust", "build"
+        ]);
+        assert_eq!(build.shell.as_deref(), Some("sh -c"));
+        assert_eq!(build.workdir.as_deref(), Some("app"));
+        let vars = build.env.as_ref().and_then(|v| v.vars.as_ref()).unwrap();
+        assert_eq!((vars["A"].as_str(), vars["B"].as_str()), ("base", "rust"));
+        let matrix = build.matrix.as_ref().unwrap();
+        assert!(matrix.parallel);
+        assert_eq!(matrix.dimensions.iter().map(|v| v.len()).collect::<Vec<_>>(), [2, 3]);
+        let test = &wf.nodes["test"];
+        assert_eq!(test.tasks.iter().map(|v| v.script.as_str()).collect::<Vec<_>>(), [
+            "test", "base"
+        ]);
+        assert_eq!(test.shell.as_deref(), Some("bash -c"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_declared_args() -> Result<()> {
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+args:
+  - { name: args.env, type: enum, values: [dev, prod], default: dev }
+  - { name: args.count, type: int, required: true }
+  - { name: args.verbose, type: bool, default: false }
+nodes:
+  a: { tasks: [{ script: "{{ args.env }} {{ args.count }}{{#if args.verbose}} v{{/if}}" }] }
+  b: { tasks: [{ script: "b" }] }
+"#,
+        )?;
+        let c = crate::compiler::Compiler::new(wf);
+        let a = ["a".to_owned()].into();
+        let args = |v: &[(&str, &str)]| v.iter().map(|v| (v.0.to_owned(), v.1.to_owned())).collect();
+
+        let plan = c.plan(&a, &args(&[("args.count", "2")]))?;
+        assert_eq!(plan.nodes["a"].tasks[0].cmds[0], "dev 2");
+        let plan = c.plan(
+            &a,
+            &args(&[("args.count", "2"), ("args.verbose", "true"), ("args.env", "prod")]),
+        )?;
+        assert_eq!(plan.nodes["a"].tasks[0].cmds[0], "prod 2 v");
+        assert!(c.plan(&a, &args(&[])).is_err());
+        assert!(c.plan(&a, &args(&[("args.count", "x")])).is_err());
+        assert!(c.plan(&a, &args(&[("args.count", "2"), ("args.env", "qa")])).is_err());
+        // required arguments are only required by nodes that use them
+        c.plan(&["b".to_owned()].into(), &args(&[]))?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_matrix_values() -> Result<()> {
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    matrix:
+      parallel: false
+      dimensions:
+        - [{ values: { os: linux } }, { values: { os: macos } }]
+        - [{ values: { arch: x64 } }]
+    tasks: [{ script: "{{ matrix.os }}-{{ matrix.arch }} {{ matrix.cell.[0] }}" }]
+"#,
+        )?;
+        let plan = crate::compiler::Compiler::new(wf).plan(&["a".to_owned()].into(), &Default::default())?;
+        assert_eq!(plan.nodes["a"].tasks[0].cmds, ["linux-x64 0", "macos-x64 1"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_matrix_include_exclude() -> Result<()> {
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    matrix:
+      parallel: false
+      dimensions:
+        - [{ values: { os: linux } }, { values: { os: macos } }]
+        - [{ values: { arch: x64 } }, { values: { arch: arm64 }, env: { vars: { ARM: "1" } } }]
+      exclude:
+        - { values: { os: linux }, env: { vars: { ARM: "1" } } }
+      include:
+        - { values: { os: windows, arch: x64 } }
+    tasks: [{ script: "{{ matrix.os }}-{{ matrix.arch }}" }]
+"#,
+        )?;
+        let mut matrix = wf.nodes["a"].matrix.clone().unwrap();
+        let plan = crate::compiler::Compiler::new(wf).plan(&["a".to_owned()].into(), &Default::default())?;
+        let node = &plan.nodes["a"];
+        assert_eq!(node.tasks[0].cmds, [
+            "linux-x64",
+            "macos-x64",
+            "macos-arm64",
+            "windows-x64"
+        ]);
+        assert_eq!(node.invocations.iter().map(|v| v.cell.clone()).collect::<Vec<_>>(), [
+            [0, 0],
+            [1, 0],
+            [1, 1],
+            [2, 2]
+        ]);
+
+        // without dimensions, only the included entries exist
+        let only_include = crate::workflow::Matrix {
+            dimensions: vec![],
+            include: matrix.include.clone(),
+            ..matrix.clone()
+        };
+        let invocations = only_include.compile()?;
+        assert_eq!(invocations.len(), 1);
+        assert_eq!(invocations[0].cell, [0]);
+        assert_eq!(invocations[0].values["os"], "windows");
+        let empty = crate::workflow::Matrix {
+            dimensions: vec![],
+            include: None,
+            ..matrix.clone()
+        };
+        assert_eq!(empty.compile()?.len(), 1);
+
+        // coordinates of included entries must fit
+        matrix.include = Some(vec![matrix.dimensions[0][0].clone(); 255]);
+        assert!(matrix.compile().is_err());
+
+        let problems = crate::lint::lint(
+            r#"version: "0.0"
+nodes:
+  a:
+    matrix:
+      parallel: false
+      dimensions: [[{ values: { os: linux } }]]
+      exclude: [{}, { env: { vars: {} } }]
+    tasks: []
+"#,
+            Path::new("neomake.yaml"),
+        )
+        .iter()
+        .map(|p| p.path.clone())
+        .collect::<Vec<_>>();
+        assert_eq!(problems, ["nodes.a.matrix.exclude[0]", "nodes.a.matrix.exclude[1]"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_scheduler() -> Result<()> {
+        use crate::exec::Scheduler;
+
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a: { tasks: [] }
+  b: { pre: [{ name: a }], tasks: [] }
+  c: { pre: [{ name: a }], tasks: [] }
+  d: { pre: [{ name: b }, { name: c }], tasks: [] }
+  e: { tasks: [] }
+"#,
+        )?;
+        let all = ["a", "b", "c", "d", "e"].map(|v| v.to_owned()).into();
+        let plan = crate::compiler::Compiler::new(wf).plan(&all, &Default::default())?;
+        let drain = |s: &mut Scheduler| {
+            let mut ready = std::iter::from_fn(|| s.next())
+                .map(|v| v.to_owned())
+                .collect::<Vec<_>>();
+            ready.sort();
+            ready
+        };
+
+        // nodes become ready once all of their prerequisites succeeded
+        let mut s = Scheduler::new(&plan, FailureMode::FailFast)?;
+        assert_eq!(drain(&mut s), ["a", "e"]);
+        s.succeed("a");
+        assert_eq!(drain(&mut s), ["b", "c"]);
+        s.succeed("b");
+        assert!(drain(&mut s).is_empty());
+        s.succeed("c");
+        assert_eq!(drain(&mut s), ["d"]);
+
+        // fail fast does not start anything after a failure
+        let mut s = Scheduler::new(&plan, FailureMode::FailFast)?;
+        let first = s.next().unwrap();
+        s.fail(first);
+        assert_eq!(s.next(), None);
+
+        // keep going starts everything that does not depend on the failed node
+        let mut s = Scheduler::new(&plan, FailureMode::KeepGoing)?;
+        assert_eq!(drain(&mut s), ["a", "e"]);
+        s.fail("a");
+        s.succeed("e");
+        assert!(drain(&mut s).is_empty());
+        let mut skipped = s.skipped().iter().copied().collect::<Vec<_>>();
+        skipped.sort();
+        assert_eq!(skipped, ["b", "c", "d"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_plan_compatibility() -> Result<()> {
+        use crate::exec::Scheduler;
+
+        // plans of older versions neither record prerequisites nor inputs, outputs and
+        // timeouts
+        let plan = serde_yaml::from_str::<crate::plan::ExecutionPlan>(
+            r#"
+version: "0.0"
+env: {}
+stages:
+  - nodes: [a, b]
+  - nodes: [c]
+  - nodes: [d]
+nodes:
+  a: { parallel: false, invocations: [{ cell: [], env: {} }], tasks: [{ cmds: [echo a], env: {} }], env: {} }
+  b: { parallel: false, invocations: [{ cell: [], env: {} }], tasks: [{ cmds: [echo b], env: {} }], env: {} }
+  c: { parallel: false, invocations: [{ cell: [], env: {} }], tasks: [{ cmds: [echo c], env: {} }], env: {} }
+  d:
+    parallel: false
+    invocations: [{ cell: [0], env: {} }, { cell: [1], env: {} }]
+    tasks: [{ cmd: echo d, env: {} }]
+    env: {}
+"#,
+        )?;
+        assert_eq!(plan.nodes["c"].pre, None);
+        assert_eq!(plan.nodes["c"].timeout, None);
+        // commands were rendered once for all matrix entries
+        assert_eq!(plan.nodes["d"].tasks[0].cmd(1), "echo d");
+        plan.validate()?;
+
+        // any other number of commands than one or one per invocation is rejected
+        for cmds in [vec![], vec!["echo d".to_owned(); 3]] {
+            let mut broken = plan.clone();
+            let cnt = cmds.len();
+            broken.nodes.get_mut("d").unwrap().tasks[0].cmds = cmds;
+            assert_eq!(
+                broken.validate().unwrap_err().to_string(),
+                format!("node d: task 0 has {} command(s) for 2 invocation(s)", cnt)
+            );
+            let engine = ExecutionEngine::new(output(false, false, OutputStyle::Inherit), FailureMode::FailFast);
+            assert!(engine.dry_run(&broken).is_err());
+        }
+
+        // their stages run one after another
+        let mut s = Scheduler::new(&plan, FailureMode::FailFast)?;
+        let mut ready = vec![s.next().unwrap(), s.next().unwrap()];
+        ready.sort();
+        assert_eq!(ready, ["a", "b"]);
+        assert_eq!(s.next(), None);
+        s.succeed("a");
+        assert_eq!(s.next(), None);
+        s.succeed("b");
+        assert_eq!(s.next(), Some("c"));
+
+        // stages and nodes have to match
+        let error = |plan: &crate::plan::ExecutionPlan| {
+            Scheduler::new(plan, FailureMode::FailFast).err().map(|v| v.to_string())
+        };
+        let mut broken = plan.clone();
+        broken.stages[2].nodes.push("x".to_owned());
+        assert_eq!(error(&broken).as_deref(), Some("stage contains unknown node x"));
+        let mut broken = plan.clone();
+        broken.stages.pop();
+        assert_eq!(error(&broken).as_deref(), Some("node d is not part of any stage"));
+        let mut broken = plan.clone();
+        broken.stages[1].nodes.push("a".to_owned());
+        assert_eq!(error(&broken).as_deref(), Some("node a is part of several stages"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_progress_resume() -> Result<()> {
+        use {
+            crate::state::{
+                Progress,
+                WorkItem,
+            },
+            std::time::{
+                Duration,
+                SystemTime,
+            },
+        };
+
+        let dir = TestDir::new("progress")?;
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    matrix: { parallel: false, dimensions: [[{}, {}]] }
+    tasks: [{ script: "echo" }]
+  b:
+    tasks: [{ script: "echo" }]
+"#,
+        )?;
+        let c = crate::compiler::Compiler::new(wf);
+        let plan = c.plan(&["a".to_owned()].into(), &Default::default())?;
+        let item = |cell: u8| {
+            WorkItem {
+                node: "a".to_owned(),
+                cell: vec![cell],
+                task: 0,
+            }
+        };
+        let runs = || {
+            std::fs::read_dir(dir.join("runs"))
+                .map(|v| v.count())
+                .unwrap_or_default()
+        };
+
+        // nothing is written until the first work item completed
+        let mut progress = Progress::open(&dir, &plan, false)?;
+        assert_eq!(runs(), 0);
+        progress.complete(item(0))?;
+        assert_eq!(runs(), 1);
+        drop(progress);
+
+        // resuming skips completed work items
+        let mut progress = Progress::open(&dir, &plan, true)?;
+        assert!(progress.is_completed(&item(0)));
+        assert!(!progress.is_completed(&item(1)));
+        progress.complete(item(1))?;
+        drop(progress);
+
+        // starting over discards the previous progress
+        let progress = Progress::open(&dir, &plan, false)?;
+        assert!(!progress.is_completed(&item(0)));
+        assert_eq!(runs(), 0);
+
+        // the progress of a succeeded plan is removed
+        let mut progress = Progress::open(&dir, &plan, false)?;
+        progress.complete(item(0))?;
+        progress.finish()?;
+        assert_eq!(runs(), 0);
+
+        // plans with a single work item are not recorded
+        let single = c.plan(&["b".to_owned()].into(), &Default::default())?;
+        Progress::open(&dir, &single, false)?.complete(WorkItem {
+            node: "b".to_owned(),
+            cell: vec![],
+            task: 0,
+        })?;
+        assert_eq!(runs(), 0);
+
+        // stale progress of other plans is pruned
+        let stale = dir.join("runs").join("stale.jsonl");
+        std::fs::write(&stale, "")?;
+        std::fs::File::options()
+            .write(true)
+            .open(&stale)?
+            .set_modified(SystemTime::now() - Duration::from_secs(8 * 24 * 60 * 60))?;
+        Progress::open(&dir, &plan, false)?;
+        assert!(!stale.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_cache() -> Result<()> {
+        use {
+            crate::cache::Cache,
+            std::time::Duration,
+        };
+
+        let dir = TestDir::new("cache")?;
+        let work = dir.join("work");
+        std::fs::create_dir_all(work.join("out"))?;
+        std::fs::write(work.join("out").join("a.txt"), "a")?;
+        std::os::unix::fs::symlink("a.txt", work.join("out").join("link"))?;
+        let wf = serde_yaml::from_str::<Workflow>(&format!(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    workdir: {}
+    outputs: [out]
+    tasks: [{{ script: "echo" }}]
+"#,
+            work.display()
+        ))?;
+        let plan = crate::compiler::Compiler::new(wf).plan(&["a".to_owned()].into(), &Default::default())?;
+        let cache = Cache::new(&dir.join("state"));
+        let fingerprint = "ab".repeat(32);
+
+        // outputs are restored from the cache
+        cache.store("a", &fingerprint, &plan.nodes["a"])?;
+        std::fs::remove_dir_all(work.join("out"))?;
+        assert!(cache.restore(&fingerprint, &plan.nodes["a"])?);
+        assert_eq!(std::fs::read_to_string(work.join("out").join("a.txt"))?, "a");
+        assert_eq!(std::fs::read_link(work.join("out").join("link"))?, Path::new("a.txt"));
+        assert!(!cache.restore(&"cd".repeat(32), &plan.nodes["a"])?);
+
+        // corrupt entries are reported instead of being used
+        let corrupt = dir.join("state").join("cache").join("entries").join("x.json");
+        std::fs::write(
+            &corrupt,
+            r#"{ "node": "x", "fingerprint": "x", "created": "2020-01-01T00:00:00Z", "files": [] }"#,
+        )?;
+        // as are entries with paths outside of the workdir
+        for (fingerprint, path) in [
+            ("ef".repeat(32), dir.join("escaped")),
+            ("fe".repeat(32), "../escaped".into()),
+        ] {
+            std::fs::write(
+                dir.join("state").join("cache").join("entries").join(format!("{}.json", fingerprint)),
+                serde_json::json!({
+                    "node": "a",
+                    "fingerprint": fingerprint,
+                    "created": "2020-01-01T00:00:00Z",
+                    "files": [],
+                    "links": [{ "path": path, "target": "a.txt" }],
+                })
+                .to_string(),
+            )?;
+            assert!(cache.restore(&fingerprint, &plan.nodes["a"]).is_err());
+        }
+        assert!(!dir.join("escaped").exists());
+        let (entries, invalid) = cache.entries()?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(invalid.len(), 3);
+        assert!(cache.restore("x", &plan.nodes["a"]).is_err());
+
+        // pruning removes corrupt and old entries as well as unreferenced blobs
+        assert_eq!(cache.prune(Duration::from_secs(60 * 60))?, (3, 0));
+        // blobs that another process still writes are kept
+        let tmp = dir.join("state").join("cache").join("blobs").join("ab").join("x.1.tmp");
+        std::fs::create_dir_all(tmp.parent().unwrap())?;
+        std::fs::write(&tmp, "x")?;
+        assert_eq!(cache.prune(Duration::ZERO)?, (1, 1));
+        assert!(!cache.restore(&fingerprint, &plan.nodes["a"])?);
+        assert!(tmp.exists());
+        std::fs::File::options()
+            .write(true)
+            .open(&tmp)?
+            .set_modified(std::time::SystemTime::now() - Duration::from_secs(2 * 60 * 60))?;
+        assert_eq!(cache.prune(Duration::ZERO)?, (0, 1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_cache_switch() -> Result<()> {
+        use crate::cache::Cache;
+
+        let dir = TestDir::new("cache-switch")?;
+        let out = dir.join("out");
+        let wf = serde_yaml::from_str::<Workflow>(&format!(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    workdir: {}
+    outputs: [out]
+    tasks: [{{ script: "echo" }}]
+"#,
+            dir.display()
+        ))?;
+        let plan = crate::compiler::Compiler::new(wf).plan(&["a".to_owned()].into(), &Default::default())?;
+        let cache = Cache::new(&dir.join("state"));
+        let (v1, v2) = ("ab".repeat(32), "cd".repeat(32));
+
+        std::fs::create_dir_all(&out)?;
+        std::fs::write(out.join("v1.txt"), "1")?;
+        cache.store("a", &v1, &plan.nodes["a"])?;
+        std::fs::remove_dir_all(&out)?;
+        std::fs::create_dir_all(&out)?;
+        std::fs::write(out.join("v2.txt"), "2")?;
+        cache.store("a", &v2, &plan.nodes["a"])?;
+
+        // restoring replaces the outputs instead of adding to them
+        assert!(cache.restore(&v1, &plan.nodes["a"])?);
+        assert_eq!(std::fs::read_dir(&out)?.count(), 1);
+        assert_eq!(std::fs::read_to_string(out.join("v1.txt"))?, "1");
+        assert!(cache.restore(&v2, &plan.nodes["a"])?);
+        assert_eq!(std::fs::read_dir(&out)?.count(), 1);
+        assert_eq!(std::fs::read_to_string(out.join("v2.txt"))?, "2");
+
+        // links in the way are replaced instead of being written through
+        std::fs::write(dir.join("outside.txt"), "x")?;
+        std::fs::remove_file(out.join("v2.txt"))?;
+        std::os::unix::fs::symlink(dir.join("outside.txt"), out.join("v2.txt"))?;
+        assert!(cache.restore(&v2, &plan.nodes["a"])?);
+        assert_eq!(std::fs::read_to_string(dir.join("outside.txt"))?, "x");
+        assert!(!out.join("v2.txt").symlink_metadata()?.file_type().is_symlink());
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_dry_run_masking() -> Result<()> {
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    env: { vars: { API_TOKEN: abc, PASSWORD: a, EMPTY_SECRET: "", NAME: abc } }
+    tasks: [{ script: "echo abc xabc a_b $API_TOKEN a" }]
+"#,
+        )?;
+        let plan = crate::compiler::Compiler::new(wf).plan(&["a".to_owned()].into(), &Default::default())?;
+        let engine = ExecutionEngine::new(output(true, true, OutputStyle::Inherit), FailureMode::FailFast);
+        let resolved = serde_yaml::from_str::<serde_yaml::Value>(&engine.dry_run(&plan)?)?;
+        // only whole words are masked, short values do not mask parts of other words
+        assert_eq!(resolved[0]["command"], "echo ******** xabc a_b $API_TOKEN ********");
+        assert_eq!(resolved[0]["env"]["API_TOKEN"], "********");
+        assert_eq!(resolved[0]["env"]["PASSWORD"], "********");
+        assert_eq!(resolved[0]["env"]["EMPTY_SECRET"], "********");
+        assert_eq!(resolved[0]["env"]["NAME"], "abc");
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_prefix_output() -> Result<()> {
+        use crate::exec::LINE_LIMIT;
+
+        assert_eq!(ExecutionEngine::prefix("a", &[], false), "[a]");
+        assert_eq!(ExecutionEngine::prefix("a", &[0, 12], false), "[a cell=0,12]");
+        // colors are stable per node and cell
+        let colored = ExecutionEngine::prefix("a", &[1], true);
+        assert!(colored.contains("[a cell=1]") && colored.starts_with('\u{1b}'));
+        assert_eq!(colored, ExecutionEngine::prefix("a", &[1], true));
+
+        assert_eq!(ExecutionEngine::prefix_line("[a]", b"line\n"), b"[a] line\n");
+        // the last line might lack a line break
+        assert_eq!(ExecutionEngine::prefix_line("[a]", b"last"), b"[a] last\n");
+        assert_eq!(ExecutionEngine::prefix_line("[a]", b"\n"), b"[a] \n");
+
+        // long lines are forwarded in parts
+        let mut source = std::io::Cursor::new([vec![b'x'; 2 * LINE_LIMIT + 1], b"\nend".to_vec()].concat());
+        let mut lines = Vec::<usize>::new();
+        let mut line = Vec::<u8>::new();
+        while ExecutionEngine::read_line(&mut source, &mut line)? > 0 {
+            lines.push(line.len());
+            line.clear();
+        }
+        assert_eq!(lines, [LINE_LIMIT, LINE_LIMIT, 2, 3]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_grouped_output() -> Result<()> {
+        use {
+            crate::exec::{
+                Captured,
+                Stream,
+            },
+            std::time::Duration,
+        };
+
+        let group = |captured: &[u8]| {
+            String::from_utf8(ExecutionEngine::format_group(
+                "a cell=0 task=1",
+                "exit code 0",
+                Duration::from_micros(1_500_300),
+                captured,
+            ))
+        };
+        assert_eq!(group(b"")?, "--- a cell=0 task=1 | exit code 0 | 1s 500ms ---\n");
+        assert_eq!(
+            group(b"out\nerr\n")?,
+            "--- a cell=0 task=1 | exit code 0 | 1s 500ms ---\nout\nerr\n"
+        );
+        // the block always ends with a line break
+        assert_eq!(
+            group(b"out")?,
+            "--- a cell=0 task=1 | exit code 0 | 1s 500ms ---\nout\n"
+        );
+
+        // output of the other stream starts on a new line
+        let mut captured = Captured::default();
+        captured.append(Stream::Stdout, b"no");
+        captured.append(Stream::Stdout, b"nl");
+        captured.append(Stream::Stderr, b"err\n");
+        captured.append(Stream::Stdout, b"out\n");
+        captured.append(Stream::Stderr, b"");
+        assert_eq!(
+            group(&captured.data)?,
+            "--- a cell=0 task=1 | exit code 0 | 1s 500ms ---\nnonl\nerr\nout\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_grouped_output_streams() -> Result<()> {
+        if let Some(mut cmd) = isolated("test::test_grouped_output_streams")? {
+            let out = cmd.output()?;
+            assert!(out.status.success());
+            // only STDOUT shown: the block with the output of STDOUT goes to STDOUT
+            let stdout = String::from_utf8(out.stdout)?;
+            assert_eq!(stdout.matches("--- a task=0 | exit code 0").count(), 1);
+            assert!(stdout.contains("VISIBLE_OUT\n"));
+            assert!(!stdout.contains("VISIBLE_ERR"));
+            // only STDERR shown: the block with the output of STDERR goes to STDERR
+            let stderr = String::from_utf8(out.stderr)?;
+            assert_eq!(stderr.matches("--- a task=0 | exit code 0").count(), 1);
+            assert!(stderr.contains("VISIBLE_ERR\n"));
+            assert!(!stderr.contains("VISIBLE_OUT"));
+            return Ok(());
+        }
+
+        let dir = TestDir::new("grouped-streams")?;
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    tasks: [{ script: "echo VISIBLE_OUT; echo VISIBLE_ERR >&2" }]
+"#,
+        )?;
+        let plan = crate::compiler::Compiler::new(wf).plan(&["a".to_owned()].into(), &Default::default())?;
+        for (stdout, stderr) in [(true, false), (false, true), (false, false)] {
+            engine(output(stdout, stderr, OutputStyle::Grouped), FailureMode::FailFast, &dir).execute(&plan, 1)?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_junit_report() -> Result<()> {
+        use {
+            crate::junit::TaskReport,
+            std::time::Duration,
+        };
+
+        let report = |node: &str, attempt: usize, failure: Option<&str>, allowed: bool, stdout: &[u8]| {
+            TaskReport {
+                node: node.to_owned(),
+                cell: vec![0, 1],
+                task: 0,
+                attempt,
+                duration: Duration::from_millis(1500),
+                failure: failure.map(|v| v.to_owned()),
+                allowed,
+                stdout: stdout.to_vec(),
+                stderr: vec![],
+            }
+        };
+        let xml = crate::junit::render(&[
+            // only the last attempt counts
+            report("a<b", 2, None, false, b"x & y\x01\n"),
+            report("a<b", 1, Some("failed"), false, b""),
+            report("c", 1, Some("exit code 3 'x' \"y\""), true, b""),
+            report("d", 3, Some("timed out"), false, b""),
+        ]);
+        assert_eq!(
+            xml,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="neomake" tests="3" failures="1" time="4.500">
+  <testsuite name="a&lt;b" tests="1" failures="0" time="1.500">
+    <testcase name="cell=0,1 task=0" classname="a&lt;b" time="1.500">
+      <system-out>x &amp; y
+</system-out>
+    </testcase>
+  </testsuite>
+  <testsuite name="c" tests="1" failures="0" time="1.500">
+    <testcase name="cell=0,1 task=0" classname="c" time="1.500">
+      <properties><property name="allowed_failure" value="exit code 3 &apos;x&apos; &quot;y&quot;"/></properties>
+    </testcase>
+  </testsuite>
+  <testsuite name="d" tests="1" failures="1" time="1.500">
+    <testcase name="cell=0,1 task=0" classname="d" time="1.500">
+      <failure message="timed out (attempt 3)" type="failure"/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_up_to_date() -> Result<()> {
+        let dir = TestDir::new("up-to-date")?;
+        std::fs::write(dir.join("in.txt"), "1")?;
+        let wf = serde_yaml::from_str::<Workflow>(&format!(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    workdir: {}
+    inputs: ["*.txt"]
+    outputs: [out.log]
+    tasks: [{{ script: "cp in.txt out.log && echo x >> runs.log" }}]
+  b:
+    tasks: [{{ script: "echo" }}]
+"#,
+            dir.display()
+        ))?;
+        let plan =
+            crate::compiler::Compiler::new(wf).plan(&["a".to_owned(), "b".to_owned()].into(), &Default::default())?;
+
+        // fingerprints are stable and only exist for nodes with inputs
+        let fingerprint = crate::state::fingerprint(&plan, &plan.nodes["a"])?.unwrap();
+        assert_eq!(
+            crate::state::fingerprint(&plan, &plan.nodes["a"])?.as_deref(),
+            Some(fingerprint.as_str())
+        );
+        assert_eq!(crate::state::fingerprint(&plan, &plan.nodes["b"])?, None);
+
+        let engine = engine(
+            output(false, false, OutputStyle::Inherit),
+            FailureMode::FailFast,
+            &dir.join("state"),
+        );
+        let runs = || std::fs::read_to_string(dir.join("runs.log")).map(|v| v.lines().count());
+
+        engine.execute(&plan, 1)?;
+        assert_eq!(runs()?, 1);
+        assert_eq!(
+            crate::state::Fingerprints::load(&dir.join("state")).get("a"),
+            Some(fingerprint.as_str())
+        );
+        // unchanged inputs with existing outputs are up to date
+        engine.execute(&plan, 1)?;
+        assert_eq!(runs()?, 1);
+        // missing outputs are restored from the cache
+        std::fs::remove_file(dir.join("out.log"))?;
+        engine.execute(&plan, 1)?;
+        assert_eq!(runs()?, 1);
+        assert_eq!(std::fs::read_to_string(dir.join("out.log"))?, "1");
+        // changed inputs change the fingerprint
+        std::fs::write(dir.join("in.txt"), "2")?;
+        assert_ne!(
+            crate::state::fingerprint(&plan, &plan.nodes["a"])?.unwrap(),
+            fingerprint
+        );
+        engine.execute(&plan, 1)?;
+        assert_eq!(runs()?, 2);
+        // unreadable fingerprints are discarded instead of failing the execution
+        std::fs::write(dir.join("state").join("fingerprints.json"), "{")?;
+        assert_eq!(crate::state::Fingerprints::load(&dir.join("state")).get("a"), None);
+        engine.execute(&plan, 1)?;
+        assert!(crate::state::Fingerprints::load(&dir.join("state")).get("a").is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_up_to_date_without_progress() -> Result<()> {
+        let dir = TestDir::new("up-to-date-without-progress")?;
+        std::fs::write(dir.join("in.txt"), "1")?;
+        // progress can not be recorded if `runs` is not a directory
+        std::fs::write(dir.join("runs"), "")?;
+        let yaml = format!(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    workdir: {}
+    inputs: [in.txt]
+    tasks: [{{ script: "echo" }}, {{ script: "echo" }}]
+"#,
+            dir.display()
+        );
+        // failing to record progress does not keep the fingerprint from being stored
+        execute(&yaml, &["a"], FailureMode::FailFast, &dir)?;
+        assert!(crate::state::Fingerprints::load(&dir).get("a").is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_graph_output() -> Result<()> {
+        use crate::args::GraphFormat;
+
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    description: says "hi" <loud>
+    matrix: { parallel: false, dimensions: [[{}, {}]] }
+    tasks: []
+  b: { pre: [{ name: a }], tasks: [] }
+"#,
+        )?;
+        let c = crate::compiler::Compiler::new(wf);
+        let nodes = ["b".to_owned()].into();
+        assert_eq!(
+            c.render_graph(&nodes, &GraphFormat::Dot, false)?,
+            "digraph neomake {\n  rankdir=LR;\n  \"a\" [label=\"a\"];\n  \"b\" [label=\"b\"];\n  \"a\" -> \"b\";\n}\n"
+        );
+        assert_eq!(
+            c.render_graph(&nodes, &GraphFormat::Dot, true)?,
+            r#"digraph neomake {
+  rankdir=LR;
+  "a" [label="a\nmatrix entries: 2\nsays \"hi\" <loud>"];
+  "b" [label="b\nmatrix entries: 1"];
+  "a" -> "b";
+}
+"#
+        );
+        assert_eq!(
+            c.render_graph(&nodes, &GraphFormat::Mermaid, false)?,
+            "graph LR\n  n0[\"a\"]\n  n1[\"b\"]\n  n0 --> n1\n"
+        );
+        assert_eq!(
+            c.render_graph(&nodes, &GraphFormat::Mermaid, true)?,
+            "graph LR\n  n0[\"a<br/>matrix entries: 2<br/>says #quot;hi#quot; #lt;loud#gt;\"]\n  n1[\"b<br/>matrix \
+             entries: 1\"]\n  n0 --> n1\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_dependents() -> Result<()> {
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a: { tasks: [] }
+  b: { pre: [{ name: a }], tasks: [] }
+  c: { pre: [{ regex: "^[ab]$" }], tasks: [] }
+  d: { pre: [{ name: c }], tasks: [] }
+  e: { tasks: [] }
+"#,
+        )?;
+        let c = crate::compiler::Compiler::new(wf);
+        let (direct, all) = c.determine_dependents(&["a".to_owned()].into())?;
+        assert_eq!(direct, vec!["b", "c"]);
+        assert_eq!(all, vec!["b", "c", "d"]);
+
+        // the given nodes are never listed as their own dependents
+        let (direct, all) = c.determine_dependents(&["a".to_owned(), "b".to_owned()].into())?;
+        assert_eq!(direct, vec!["c"]);
+        assert_eq!(all, vec!["c", "d"]);
+
+        let (direct, all) = c.determine_dependents(&["e".to_owned()].into())?;
+        assert!(direct.is_empty() && all.is_empty());
+
+        assert!(c.determine_dependents(&["x".to_owned()].into()).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_explain() -> Result<()> {
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a: { tasks: [] }
+  b: { pre: [{ name: a }], tasks: [] }
+  c: { pre: [{ regex: "^[ab]$" }], tasks: [] }
+  d: { pre: [{ name: b }, { name: c }], tasks: [] }
+  e: { tasks: [] }
+"#,
+        )?;
+        let c = crate::compiler::Compiler::new(wf);
+        let lines = c
+            .determine_chains("d", "a")?
+            .unwrap()
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![
+            "  -> b (pre name)",
+            "    -> a (pre name)",
+            "  -> c (pre name)",
+            "    -> a (pre regex \"^[ab]$\")",
+            "    -> b (pre regex \"^[ab]$\", see above)",
+        ]);
+        assert!(c.determine_chains("d", "d")?.unwrap().is_empty());
+        assert!(c.determine_chains("e", "a")?.is_none());
+        assert!(c.determine_chains("d", "x").is_err());
+
+        // layered diamonds have exponentially many paths but every node is
+        // only expanded once
+        let mut yaml = "version: \"0.0\"\nnodes:\n  l0a: { tasks: [] }\n  l0b: { tasks: [] }\n".to_owned();
+        for layer in 1..30 {
+            for side in ["a", "b"] {
+                yaml.push_str(&format!(
+                    "  l{}{}: {{ pre: [{{ name: l{}a }}, {{ name: l{}b }}], tasks: [] }}\n",
+                    layer,
+                    side,
+                    layer - 1,
+                    layer - 1
+                ));
+            }
+        }
+        let c = crate::compiler::Compiler::new(serde_yaml::from_str::<Workflow>(&yaml)?);
+        let steps = c.determine_chains("l29a", "l0a")?.unwrap();
+        assert_eq!(steps.len(), 112);
+        assert_eq!(steps.iter().filter(|v| v.repeated).count(), 54);
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_lint_includes() -> Result<()> {
+        let dir = TestDir::new("lint-includes")?;
+        std::fs::create_dir_all(dir.join("svc"))?;
+        let root = r#"version: "0.0"
+include:
+  - path: svc/neomake.yaml
+nodes:
+  all: { pre: [{ name: "svc:build" }], tasks: [] }
+"#;
+        std::fs::write(dir.join("neomake.yaml"), root)?;
+        std::fs::write(
+            dir.join("svc/neomake.yaml"),
+            r#"version: "0.0"
+nodes:
+  setup: { tasks: [] }
+  build:
+    pre:
+      - name: setup
+      - name: missing
+    tasks: []
+"#,
+        )?;
+        let problems = crate::lint::lint(root, &dir.join("neomake.yaml"));
+        assert_eq!(problems.len(), 1);
+        assert_eq!(
+            problems[0].file,
+            Some(dir.join("svc/neomake.yaml").display().to_string())
+        );
+        assert_eq!(problems[0].path, "nodes.build.pre[1].name");
+        assert_eq!(problems[0].position, Some((7, 9)));
+
+        // included workflows may reference nodes of the including one, recursions are
+        // found across files
+        std::fs::write(
+            dir.join("svc/neomake.yaml"),
+            "version: \"0.0\"\nnodes:\n  build: { pre: [{ name: all }], tasks: [] }\n",
+        )?;
+        let problems = crate::lint::lint(root, &dir.join("neomake.yaml"));
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].file, None);
+        assert!(problems[0]
+            .message
+            .starts_with("found recursion in dag: all -> svc:build -> all"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_events_on_stdout() -> Result<()> {
+        use crate::events::EventWriter;
+
+        if let Some(mut cmd) = isolated("test::test_events_on_stdout")? {
+            let out = cmd.output()?;
+            assert!(out.status.success());
+            let stdout = String::from_utf8(out.stdout)?;
+            // the test harness does not end its own line before the first event
+            let events = stdout
+                .lines()
+                .filter_map(|v| v.find('{').map(|idx| &v[idx..]))
+                .collect::<Vec<_>>();
+            // plan_started, stage_started, task_started, task_finished and run_finished per
+            // output style
+            assert_eq!(events.len(), 3 * 5);
+            for event in events {
+                serde_json::from_str::<serde_json::Value>(event)?;
+            }
+            assert!(!stdout.contains("task output"));
+            let stderr = String::from_utf8(out.stderr)?;
+            assert_eq!(stderr.matches("task output").count(), 3);
+            assert!(stderr.contains("[a] task output\n"));
+            return Ok(());
+        }
+
+        let dir = TestDir::new("events")?;
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    tasks: [{ script: "echo task output" }]
+"#,
+        )?;
+        let plan = crate::compiler::Compiler::new(wf).plan(&["a".to_owned()].into(), &Default::default())?;
+        for style in [
+            OutputStyle::Inherit,
+            OutputStyle::Prefix { color: false },
+            OutputStyle::Grouped,
+        ] {
+            let output = OutputMode {
+                redirect_stdout: true,
+                ..output(true, true, style)
+            };
+            engine(output, FailureMode::FailFast, &dir)
+                .with_events(EventWriter::new(Box::new(std::io::stdout())))
+                .execute(&plan, 1)?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_background_processes() -> Result<()> {
+        let dir = TestDir::new("background")?;
+        let marker = dir.join("survived");
+        let wf = serde_yaml::from_str::<Workflow>(&format!(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    tasks:
+      - {{ timeout: 2s, script: "sleep 6 & echo started" }}
+      - {{ script: "(sleep 1; touch {}) >/dev/null 2>&1 &" }}
+"#,
+            marker.display()
+        ))?;
+        let plan = crate::compiler::Compiler::new(wf).plan(&["a".to_owned()].into(), &Default::default())?;
+        for style in [
+            OutputStyle::Inherit,
+            OutputStyle::Prefix { color: false },
+            OutputStyle::Grouped,
+        ] {
+            let _ = std::fs::remove_file(&marker);
+            let engine = engine(output(true, true, style), FailureMode::FailFast, &dir);
+            // processes left in the background neither keep the output of a task open
+            // nor are they killed once the task exits
+            let started = std::time::Instant::now();
+            engine.execute(&plan, 1)?;
+            assert!(started.elapsed() < std::time::Duration::from_secs(2));
+            let until = std::time::Instant::now() + std::time::Duration::from_secs(5);
+            while !marker.exists() && std::time::Instant::now() < until {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            assert!(marker.exists(), "background process of {:?} was killed", style);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_background_output() -> Result<()> {
+        if let Some(mut cmd) = isolated("test::test_background_output")? {
+            let out = cmd.stdout(std::process::Stdio::null()).output()?;
+            assert!(out.status.success());
+            let stderr = String::from_utf8(out.stderr)?;
+            assert!(stderr.contains(
+                "warning: a task=0: stopped reading the output of processes the task left running\n"
+            ));
+            return Ok(());
+        }
+
+        let dir = TestDir::new("background-output")?;
+        let wf = serde_yaml::from_str::<Workflow>(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    tasks: [{ script: "yes &" }]
+"#,
+        )?;
+        let plan = crate::compiler::Compiler::new(wf).plan(&["a".to_owned()].into(), &Default::default())?;
+        // output of processes left in the background is only read for a while
+        let started = std::time::Instant::now();
+        engine(output(true, true, OutputStyle::Prefix { color: false }), FailureMode::FailFast, &dir)
+            .execute(&plan, 1)?;
+        assert!(started.elapsed() < std::time::Duration::from_secs(3));
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_failure_modes() -> Result<()> {
+        let dir = TestDir::new("failure-modes")?;
+        let yaml = format!(
+            r#"
+version: "0.0"
+nodes:
+  fail: {{ workdir: {0}, tasks: [{{ script: "sleep 0.2; exit 1" }}] }}
+  long: {{ workdir: {0}, tasks: [{{ script: "sleep 5; touch long" }}] }}
+  ok: {{ workdir: {0}, tasks: [{{ script: "touch ok" }}] }}
+  after: {{ workdir: {0}, pre: [{{ name: fail }}], tasks: [{{ script: "touch after" }}] }}
+  last: {{ workdir: {0}, pre: [{{ name: after }}], tasks: [{{ script: "touch last" }}] }}
+"#,
+            dir.display()
+        );
+
+        // running siblings are killed right away
+        let started = std::time::Instant::now();
+        let err = execute(&yaml, &["fail", "long"], FailureMode::FailFast, &dir).unwrap_err();
+        assert!(started.elapsed() < std::time::Duration::from_secs(3));
+        assert_eq!(
+            err.to_string(),
+            "node fail: command: sleep 0.2; exit 1 failed to execute with code 1\ncancelled: long"
+        );
+        assert!(!dir.join("long").exists());
+
+        // independent nodes still run, dependents of the failed node are skipped
+        let err = execute(&yaml, &["fail", "ok", "last"], FailureMode::KeepGoing, &dir).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "node fail: command: sleep 0.2; exit 1 failed to execute with code 1\nskipped due to failed \
+             prerequisites: after, last"
+        );
+        assert!(dir.join("ok").exists());
+        assert!(!dir.join("after").exists() && !dir.join("last").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_signal_forwarding() -> Result<()> {
+        use std::time::{
+            Duration,
+            Instant,
+        };
+
+        if let Some(mut cmd) = isolated("test::test_signal_forwarding")? {
+            let dir = TestDir::new("signals")?;
+            let mut child = cmd
+                .env("NEOMAKE_TEST_DIR", &*dir)
+                .stdout(std::process::Stdio::null())
+                .spawn()?;
+            // signals are only forwarded while tasks are running
+            let started = Instant::now();
+            while !dir.join("started").exists() {
+                assert!(started.elapsed() < Duration::from_secs(10), "the task did not start");
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+            }
+            assert!(child.wait()?.success());
+            // the task handled the signal itself instead of being killed after the grace
+            // period
+            assert!(started.elapsed() < Duration::from_secs(5));
+            assert!(dir.join("terminated").exists());
+            return Ok(());
+        }
+
+        let dir = std::path::PathBuf::from(std::env::var("NEOMAKE_TEST_DIR")?);
+        let yaml = format!(
+            r#"
+version: "0.0"
+nodes:
+  a:
+    workdir: {}
+    tasks: [{{ script: "trap 'touch terminated; exit 0' TERM; touch started; sleep 10 & wait" }}]
+"#,
+            dir.display()
+        );
+        let err = execute(&yaml, &["a"], FailureMode::FailFast, &dir).unwrap_err();
+        assert_eq!(err.to_string(), "interrupted by SIGTERM\ninterrupted: a task=0");
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_timeouts() -> Result<()> {
+        let dir = TestDir::new("timeouts")?;
+        let yaml = r#"
+version: "0.0"
+nodes:
+  task:
+    tasks: [{ timeout: 200ms, script: "sleep 5" }]
+  node:
+    timeout: 1s
+    tasks: [{ script: "sleep 0.6" }, { script: "sleep 0.6" }, { script: "echo never" }]
+"#;
+        let started = std::time::Instant::now();
+        let err = execute(yaml, &["task"], FailureMode::FailFast, &dir).unwrap_err();
+        assert!(started.elapsed() < std::time::Duration::from_secs(3));
+        assert_eq!(
+            err.to_string(),
+            "node task: command: sleep 5 timed out after 200ms (task timeout)"
+        );
+
+        // the timeout of a node spans all of its tasks
+        let err = execute(yaml, &["node"], FailureMode::FailFast, &dir).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "node node: command: sleep 0.6 timed out after 1s (node timeout)"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_retries() -> Result<()> {
+        let dir = TestDir::new("retries")?;
+        let counter = dir.join("counter");
+        let yaml = format!(
+            r#"
+version: "0.0"
+nodes:
+  flaky:
+    retry: {{ attempts: 3 }}
+    tasks: [{{ script: "echo x >> {counter}; [ $(wc -l < {counter}) -ge 2 ]" }}]
+  broken:
+    retry: {{ attempts: 3, delay: 10ms }}
+    tasks: [{{ script: "exit 3" }}]
+  fatal:
+    tasks: [{{ retry: {{ attempts: 3, exit_codes: [3] }}, script: "exit 4" }}]
+"#,
+            counter = counter.display()
+        );
+
+        execute(&yaml, &["flaky"], FailureMode::FailFast, &dir)?;
+        assert_eq!(std::fs::read_to_string(&counter)?.lines().count(), 2);
+
+        let err = execute(&yaml, &["broken"], FailureMode::FailFast, &dir).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "node broken: command: exit 3 failed after 3 attempt(s)\n  attempt 1: failed to execute with code 3\n  \
+             attempt 2: failed to execute with code 3\n  attempt 3: failed to execute with code 3"
+        );
+
+        // exit codes that are not listed are not retried
+        let err = execute(&yaml, &["fatal"], FailureMode::FailFast, &dir).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "node fatal: command: exit 4 failed after 1 attempt(s)\n  attempt 1: failed to execute with code 4"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_allow_failure() -> Result<()> {
+        if let Some(mut cmd) = isolated("test::test_allow_failure")? {
+            let out = cmd.output()?;
+            assert!(out.status.success());
+            let stderr = String::from_utf8(out.stderr)?;
+            assert!(stderr
+                .contains("warning: node allowed: command: exit 7 failed to execute with code 7 (failure allowed)\n"));
+            return Ok(());
+        }
+
+        let dir = TestDir::new("allow-failure")?;
+        let marker = dir.join("after");
+        let yaml = format!(
+            r#"
+version: "0.0"
+nodes:
+  allowed:
+    allow_failure: [7]
+    tasks: [{{ script: "exit 7" }}, {{ script: "touch {marker}" }}]
+  denied:
+    tasks: [{{ allow_failure: [7], script: "exit 8" }}]
+"#,
+            marker = marker.display()
+        );
+
+        // the following tasks of the node still run after an allowed failure
+        execute(&yaml, &["allowed"], FailureMode::FailFast, &dir)?;
+        assert!(marker.exists());
+
+        let err = execute(&yaml, &["denied"], FailureMode::FailFast, &dir).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "node denied: command: exit 8 failed to execute with code 8"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    pub async fn test_run_command() -> Result<()> {
+        let dir = TestDir::new("run")?;
+        let workflow = dir.join("neomake.yaml");
+        let out = dir.join("out");
+        std::fs::write(
+            &workflow,
+            format!(
+                r#"
+version: "0.0"
+nodes:
+  a:
+    tasks: [{{ script: "echo {{{{ args.msg }}}} >> {out}" }}]
+  b:
+    pre: [{{ name: a }}]
+    tasks: [{{ script: "echo b >> {out}" }}]
+  c:
+    tasks: [{{ script: "echo c >> {out}" }}]
+"#,
+                out = out.display()
+            ),
+        )?;
+
+        let cmd = crate::args::ClapArgumentLoader::load_from([
+            "neomake",
+            "run",
+            "--workflow",
+            workflow.to_str().unwrap(),
+            "-n",
+            "b",
+            "-a",
+            "args.msg=hello",
+            "--state-dir",
+            dir.to_str().unwrap(),
+            "--no-stdout",
+        ])?;
+        match cmd.command {
+            | crate::args::Command::Run {
+                workflow,
+                nodes,
+                args,
+                execution,
+            } => crate::run(&workflow, nodes, &args, &execution)?,
+            | _ => panic!("expected the run command"),
+        }
+        // prerequisites run first, unselected nodes do not run at all
+        assert_eq!(std::fs::read_to_string(&out)?, "hello\nb\n");
+        Ok(())
+    }
 }
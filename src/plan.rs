@@ -1,4 +1,18 @@
-use std::collections::HashMap;
+use {
+    crate::workflow::{
+        AllowFailure,
+        Retry,
+    },
+    anyhow::Result,
+    itertools::Itertools,
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        time::Duration,
+    },
+};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
@@ -9,6 +23,42 @@ pub(crate) struct ExecutionPlan {
     pub env: HashMap<String, String>,
 }
 
+impl ExecutionPlan {
+    /// Checks the consistency of a plan that might have been written or edited
+    /// by hand before anything is executed.
+    pub fn validate(&self) -> Result<()> {
+        // every node is started from exactly one stage
+        let mut staged = HashSet::<&str>::new();
+        for name in self.stages.iter().flat_map(|v| v.nodes.iter()) {
+            if !self.nodes.contains_key(name) {
+                Err(anyhow::anyhow!("stage contains unknown node {}", name))?
+            }
+            if !staged.insert(name) {
+                Err(anyhow::anyhow!("node {} is part of several stages", name))?
+            }
+        }
+        if let Some(name) = self.nodes.keys().sorted().find(|v| !staged.contains(v.as_str())) {
+            Err(anyhow::anyhow!("node {} is not part of any stage", name))?
+        }
+
+        for (name, node) in self.nodes.iter().sorted_by_key(|v| v.0) {
+            for (idx, task) in node.tasks.iter().enumerate() {
+                // plans of older versions contain a single command
+                if task.cmds.len() != 1 && task.cmds.len() != node.invocations.len() {
+                    Err(anyhow::anyhow!(
+                        "node {}: task {} has {} command(s) for {} invocation(s)",
+                        name,
+                        idx,
+                        task.cmds.len(),
+                        node.invocations.len()
+                    ))?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub(crate) struct Stage {
@@ -18,28 +68,76 @@ pub(crate) struct Stage {
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub(crate) struct Node {
+    /// Names of the nodes that need to finish before this one can start. Not
+    /// set in plans of older versions, their stages run one after another.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre: Option<Vec<String>>,
     pub parallel: bool,
     pub invocations: Vec<Invocation>,
     pub tasks: Vec<Task>,
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub outputs: Vec<String>,
 
     pub env: HashMap<String, String>,
     pub shell: Option<String>,
     pub workdir: Option<String>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    pub retry: Option<Retry>,
+    pub allow_failure: Option<AllowFailure>,
 }
 
 #[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub(crate) struct Invocation {
     pub cell: Vec<u8>,
+    /// Values of the matrix entry, available as `matrix.<name>` in templates.
+    #[serde(default)]
+    pub values: HashMap<String, String>,
     pub env: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub(crate) struct Task {
-    pub cmd: String,
+    /// The rendered command for every invocation (in the order of
+    /// `Node::invocations`).
+    #[serde(alias = "cmd", deserialize_with = "one_or_many")]
+    pub cmds: Vec<String>,
 
     pub env: HashMap<String, String>,
     pub shell: Option<String>,
     pub workdir: Option<String>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    pub retry: Option<Retry>,
+    pub allow_failure: Option<AllowFailure>,
+}
+
+impl Task {
+    /// The rendered command for the invocation with the given index. Plans of
+    /// older versions contain a single command for all invocations.
+    pub fn cmd(&self, invocation: usize) -> &str {
+        match self.cmds.as_slice() {
+            | [cmd] => cmd,
+            | cmds => &cmds[invocation],
+        }
+    }
+}
+
+/// Accepts a single command (`cmd` in plans of older versions) as well as one
+/// command per invocation.
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        | OneOrMany::One(v) => vec![v],
+        | OneOrMany::Many(v) => v,
+    })
 }
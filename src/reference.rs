@@ -1,5 +1,11 @@
 use {
-    crate::args::ClapArgumentLoader,
+    crate::{
+        args::ClapArgumentLoader,
+        workflow::{
+            Argument,
+            ArgumentType,
+        },
+    },
     anyhow::Result,
     clap_complete::Shell,
     clap_mangen::Man,
@@ -23,25 +29,54 @@ fn collect_commands() -> Vec<(String, clap::Command)> {
     cmds
 }
 
-pub fn build_shell_completion(outdir: &Path, shell: &Shell) -> Result<()> {
+/// Renders the completion scripts. The values of declared enum and bool
+/// arguments are offered as values of `--arg` (only for completion, the values
+/// are validated when planning). Other arguments have no known values.
+pub(crate) fn build_shell_completion(outdir: &Path, shell: &Shell, args: &[Argument]) -> Result<()> {
     let mut app = ClapArgumentLoader::root_command();
-    clap_complete::generate_to(*shell, &mut app, "neomake", &outdir)?;
+    let values = args
+        .iter()
+        .flat_map(|arg| {
+            let values = match arg.kind {
+                | ArgumentType::Enum => arg.values.clone().unwrap_or_default(),
+                | ArgumentType::Bool => vec!["true".to_owned(), "false".to_owned()],
+                | _ => vec![],
+            };
+            values.into_iter().map(|v| {
+                let value = clap::builder::PossibleValue::new(format!("{}={}", arg.name, v));
+                match &arg.description {
+                    | Some(desc) => value.help(desc.clone()),
+                    | None => value,
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    if !values.is_empty() {
+        for cmd in ["plan", "run"] {
+            app = app.mut_subcommand(cmd, |c| {
+                c.mut_arg("arg", |a| {
+                    a.value_parser(clap::builder::PossibleValuesParser::new(values.clone()))
+                })
+            });
+        }
+    }
+    clap_complete::generate_to(*shell, &mut app, "neomake", outdir)?;
 
     Ok(())
 }
 
 pub fn build_markdown(outdir: &Path) -> Result<()> {
     for cmd in collect_commands() {
-        let file = Path::new(&outdir).join(&format!("{}.md", cmd.0.strip_prefix("-").unwrap()));
+        let file = Path::new(&outdir).join(format!("{}.md", cmd.0.strip_prefix("-").unwrap()));
         let mut file = File::create(&file)?;
-        file.write(clap_markdown::help_markdown_command(&cmd.1).as_bytes())?;
+        file.write_all(clap_markdown::help_markdown_command(&cmd.1).as_bytes())?;
     }
     Ok(())
 }
 
 pub fn build_manpages(outdir: &Path) -> Result<()> {
     for cmd in collect_commands() {
-        let file = Path::new(&outdir).join(&format!("{}.1", cmd.0.strip_prefix("-").unwrap()));
+        let file = Path::new(&outdir).join(format!("{}.1", cmd.0.strip_prefix("-").unwrap()));
         let mut file = File::create(&file)?;
         Man::new(cmd.1).render(&mut file)?;
     }
@@ -0,0 +1,260 @@
+use {
+    crate::{
+        cache::Cache,
+        plan,
+    },
+    anyhow::Result,
+    itertools::Itertools,
+    sha2::{
+        Digest,
+        Sha256,
+    },
+    std::{
+        collections::{
+            BTreeMap,
+            HashSet,
+        },
+        io::Write,
+        path::{
+            Path,
+            PathBuf,
+        },
+        time::{
+            Duration,
+            SystemTime,
+        },
+    },
+};
+
+/// Default directory (relative to the current working directory) that holds
+/// all local state of neomake.
+pub(crate) const DEFAULT_STATE_DIR: &str = ".neomake";
+
+/// Progress of executions that was not touched for this long is discarded.
+const RUN_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The fingerprints of the last successful execution of every node, stored in
+/// `fingerprints.json` in the state directory.
+#[derive(Debug)]
+pub(crate) struct Fingerprints {
+    path: PathBuf,
+    nodes: BTreeMap<String, String>,
+}
+
+impl Fingerprints {
+    /// Loads the stored fingerprints. Fingerprints that can not be read are
+    /// discarded with a warning, all nodes are outdated then.
+    pub fn load(state_dir: &Path) -> Self {
+        let path = state_dir.join("fingerprints.json");
+        let nodes = if path.exists() {
+            let nodes = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|v| Ok(serde_json::from_str(&v)?));
+            nodes.unwrap_or_else(|e| {
+                eprintln!("warning: discarding unreadable {}: {}", path.display(), e);
+                BTreeMap::new()
+            })
+        } else {
+            BTreeMap::new()
+        };
+        Self { path, nodes }
+    }
+
+    pub fn get(&self, node: &str) -> Option<&str> {
+        self.nodes.get(node).map(|v| v.as_str())
+    }
+
+    /// Records the fingerprint of a successful execution and persists all
+    /// fingerprints right away, without leaving a partial file behind if the
+    /// process is killed meanwhile.
+    pub fn set(&mut self, node: &str, fingerprint: String) -> Result<()> {
+        self.nodes.insert(node.to_owned(), fingerprint);
+        Cache::write_atomic(&self.path, serde_json::to_string_pretty(&self.nodes)?.as_bytes())
+    }
+}
+
+/// Resolves the glob patterns relative to the workdir of the node. Returns one
+/// (sorted) list of paths per pattern.
+pub(crate) fn resolve(workdir: Option<&str>, patterns: &[String]) -> Result<Vec<Vec<PathBuf>>> {
+    let base = Path::new(workdir.unwrap_or("."));
+    patterns
+        .iter()
+        .map(|p| {
+            let pattern = base.join(p);
+            let pattern = pattern.to_str().ok_or(anyhow::anyhow!("invalid glob pattern: {}", p))?;
+            let paths = glob::glob(pattern)
+                .map_err(|e| anyhow::anyhow!("invalid glob pattern {}: {}", p, e))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(paths.into_iter().sorted().collect())
+        })
+        .collect()
+}
+
+/// Computes the fingerprint of a node from the contents of its inputs and its
+/// rendered tasks, matrix entries, env and settings. Returns `None` if the node
+/// does not declare any inputs and can therefore never be up to date.
+pub(crate) fn fingerprint(plan: &plan::ExecutionPlan, node: &plan::Node) -> Result<Option<String>> {
+    if node.inputs.is_empty() {
+        return Ok(None);
+    }
+
+    let mut hasher = Sha256::new();
+    let mut feed = |v: &[u8]| {
+        hasher.update((v.len() as u64).to_le_bytes());
+        hasher.update(v);
+    };
+
+    // maps in serde_json values are sorted which makes this stable
+    let definition = serde_json::json!({
+        "env": plan.env,
+        "node": node,
+    });
+    feed(definition.to_string().as_bytes());
+
+    let files = resolve(node.workdir.as_deref(), &node.inputs)?
+        .into_iter()
+        .flatten()
+        .filter(|v| v.is_file())
+        .sorted()
+        .dedup();
+    for file in files {
+        feed(file.to_string_lossy().as_bytes());
+        feed(&std::fs::read(&file).map_err(|e| anyhow::anyhow!("failed to read {}: {}", file.display(), e))?);
+    }
+
+    Ok(Some(format!("{:x}", hasher.finalize())))
+}
+
+/// Whether every output pattern of the node matches at least one path.
+pub(crate) fn outputs_exist(node: &plan::Node) -> Result<bool> {
+    Ok(resolve(node.workdir.as_deref(), &node.outputs)?
+        .iter()
+        .all(|v| !v.is_empty()))
+}
+
+/// Identifies a single work item, i.e. a task for one matrix entry of a node.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) struct WorkItem {
+    pub node: String,
+    pub cell: Vec<u8>,
+    pub task: usize,
+}
+
+/// The work items that completed successfully during the execution of a plan,
+/// stored as newline delimited JSON in `runs/<plan hash>.jsonl` in the state
+/// directory. Plans with a single work item are not recorded since there is
+/// nothing that could be skipped when resuming them.
+pub(crate) struct Progress {
+    path: PathBuf,
+    completed: HashSet<WorkItem>,
+    /// Whether the progress is recorded at all.
+    record: bool,
+    /// Opened once the first work item is recorded.
+    file: Option<std::fs::File>,
+}
+
+impl Progress {
+    /// Opens the progress of the given plan. Unless resuming, the progress of
+    /// previous executions of the same plan is discarded. Progress of other
+    /// plans that was not touched for a week is removed.
+    pub fn open(state_dir: &Path, plan: &plan::ExecutionPlan, resume: bool) -> Result<Self> {
+        let hash = format!(
+            "{:x}",
+            Sha256::digest(serde_json::to_value(plan)?.to_string().as_bytes())
+        );
+        let dir = state_dir.join("runs");
+        let path = dir.join(format!("{}.jsonl", hash));
+        if let Err(e) = Self::prune(&dir, &path, RUN_RETENTION) {
+            eprintln!("warning: failed to remove stale progress: {}", e);
+        }
+
+        let mut completed = HashSet::<WorkItem>::new();
+        if resume {
+            if path.exists() {
+                for line in std::fs::read_to_string(&path)?.lines() {
+                    // the last line might be incomplete if neomake was killed
+                    if let Ok(v) = serde_json::from_str(line) {
+                        completed.insert(v);
+                    }
+                }
+            } else {
+                eprintln!("warning: nothing to resume for this plan, executing everything");
+            }
+        } else if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let items = plan
+            .nodes
+            .values()
+            .map(|v| v.invocations.len() * v.tasks.len())
+            .sum::<usize>();
+        Ok(Self {
+            path,
+            completed,
+            record: items > 1,
+            file: None,
+        })
+    }
+
+    /// Removes all progress files in the directory except `keep` that were not
+    /// modified within the given duration.
+    fn prune(dir: &Path, keep: &Path, retention: Duration) -> Result<()> {
+        if !dir.exists() {
+            return Ok(());
+        }
+        let threshold = SystemTime::now() - retention;
+        for file in std::fs::read_dir(dir)? {
+            let path = file?.path();
+            if path == keep || path.extension().is_none_or(|v| v != "jsonl") {
+                continue;
+            }
+            if std::fs::metadata(&path)?.modified()? < threshold {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_completed(&self, item: &WorkItem) -> bool {
+        self.completed.contains(item)
+    }
+
+    /// Records a successful work item right away.
+    pub fn complete(&mut self, item: WorkItem) -> Result<()> {
+        if self.record && self.file.is_none() {
+            std::fs::create_dir_all(self.path.parent().unwrap())?;
+            let file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path);
+            match file {
+                | Ok(v) => self.file = Some(v),
+                | Err(e) => {
+                    // the execution itself is not affected
+                    self.record = false;
+                    Err(anyhow::anyhow!(
+                        "failed to open {}, the execution can not be resumed: {}",
+                        self.path.display(),
+                        e
+                    ))?
+                },
+            }
+        }
+        if let Some(file) = &mut self.file {
+            let mut line = serde_json::to_vec(&item)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+            file.flush()?;
+        }
+        self.completed.insert(item);
+        Ok(())
+    }
+
+    /// Discards the progress once the whole plan succeeded.
+    pub fn finish(&mut self) -> Result<()> {
+        self.file = None;
+        if self.path.exists() {
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
@@ -2,8 +2,15 @@ use {
     anyhow::Result,
     itertools::Itertools,
     std::{
-        collections::HashMap,
-        path::Path,
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        path::{
+            Path,
+            PathBuf,
+        },
+        time::Duration,
     },
 };
 
@@ -17,6 +24,17 @@ pub(crate) struct Workflow {
     /// Env vars.
     pub env: Option<Env>,
 
+    /// Other workflow files whose nodes are added to this workflow.
+    pub include: Option<Vec<Include>>,
+
+    /// Declarations of the arguments for handlebars placeholders (`-a`).
+    pub args: Option<Vec<Argument>>,
+
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    #[schemars(with = "Option<HashMap<String, Node>>")]
+    /// Reusable node definitions that nodes can extend.
+    pub templates: Option<HashMap<String, Node>>,
+
     // limiting enum ser/deser to be JSON compatible 1-entry maps (due to schema coming from schemars)
     #[serde(with = "serde_yaml::with::singleton_map_recursive")]
     #[schemars(with = "HashMap<String, Node>")]
@@ -33,25 +51,214 @@ impl Workflow {
             version: String,
         }
         let v = serde_yaml::from_str::<Versioned>(&data)?;
+        Self::check_version(&v.version)?;
+
+        let mut wf: crate::workflow::Workflow = serde_yaml::from_str(&data)?;
+        for node in wf.nodes.keys() {
+            Self::check_node_name(node)?;
+        }
+        wf.resolve_templates()?;
+        wf.resolve_includes(file.as_ref(), &mut vec![])?;
+        for (name, node) in &wf.nodes {
+            let retries = node.retry.iter().chain(node.tasks.iter().flat_map(|t| t.retry.iter()));
+            for retry in retries {
+                retry
+                    .validate()
+                    .map_err(|e| anyhow::anyhow!("node {}: invalid retry policy: {}", name, e))?;
+            }
+        }
+        Ok(wf)
+    }
+
+    /// Loads all included workflows (recursively) and merges their nodes into
+    /// this one. `file` is the path of this workflow, includes are resolved
+    /// relative to its directory.
+    pub(crate) fn resolve_includes(&mut self, file: &Path, stack: &mut Vec<PathBuf>) -> Result<()> {
+        let includes = match self.include.take() {
+            | Some(v) => v,
+            | None => return Ok(()),
+        };
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+        if stack.contains(&canonical) {
+            Err(anyhow::anyhow!("recursive include of {}", file.display()))?
+        }
+        stack.push(canonical);
+
+        let base = file.parent().unwrap_or(Path::new(""));
+        for include in includes {
+            for included_file in include.files(base)? {
+                let namespace = match &include.namespace {
+                    | Some(v) => v.clone(),
+                    | None => {
+                        included_file
+                            .canonicalize()?
+                            .parent()
+                            .and_then(|v| v.file_name())
+                            .map(|v| v.to_string_lossy().into_owned())
+                            .ok_or(anyhow::anyhow!(
+                                "can not derive a namespace for {}",
+                                included_file.display()
+                            ))?
+                    },
+                };
+                Self::check_node_name(&namespace).map_err(|_| anyhow::anyhow!("invalid namespace: {}", namespace))?;
+
+                let data = std::fs::read_to_string(&included_file)?;
+                let mut included = serde_yaml::from_str::<Workflow>(&data)
+                    .map_err(|e| anyhow::anyhow!("failed to load {}: {}", included_file.display(), e))?;
+                Self::check_version(&included.version)?;
+                included.resolve_templates()?;
+                included.resolve_includes(&included_file, stack)?;
+                self.merge(&namespace, included_file.parent().unwrap_or(Path::new("")), included)?;
+            }
+        }
+
+        stack.pop();
+        Ok(())
+    }
 
+    /// Expands every node that extends a template. Templates can extend other
+    /// templates.
+    pub(crate) fn resolve_templates(&mut self) -> Result<()> {
+        let templates = self.templates.take().unwrap_or_default();
+        let mut expanded = HashMap::<String, Node>::new();
+        // expanding unused templates as well reveals their recursions
+        for name in templates.keys().sorted() {
+            Self::expand_template(name, &templates, &mut expanded, &mut vec![])?;
+        }
+        for (name, node) in self.nodes.iter_mut().sorted_by_key(|v| v.0.clone()) {
+            if let Some(extends) = node.extends.take() {
+                let template = Self::expand_template(extends.template(), &templates, &mut expanded, &mut vec![])
+                    .map_err(|e| anyhow::anyhow!("failed to expand node {}: {}", name, e))?;
+                *node = node.clone().extend(&template, extends.tasks());
+            }
+        }
+        Ok(())
+    }
+
+    fn expand_template(
+        name: &str,
+        templates: &HashMap<String, Node>,
+        expanded: &mut HashMap<String, Node>,
+        stack: &mut Vec<String>,
+    ) -> Result<Node> {
+        if let Some(v) = expanded.get(name) {
+            return Ok(v.clone());
+        }
+        if stack.iter().any(|v| v == name) {
+            stack.push(name.to_owned());
+            Err(anyhow::anyhow!("found recursion in templates: {}", stack.join(" -> ")))?
+        }
+        let mut template = templates
+            .get(name)
+            .ok_or(anyhow::anyhow!("template not found: {}", name))?
+            .clone();
+        if let Some(extends) = template.extends.take() {
+            stack.push(name.to_owned());
+            let base = Self::expand_template(extends.template(), templates, expanded, stack)?;
+            stack.pop();
+            template = template.extend(&base, extends.tasks());
+        }
+        expanded.insert(name.to_owned(), template.clone());
+        Ok(template)
+    }
+
+    /// Adds the nodes of an included workflow as `<namespace>:<node>`. Name
+    /// selectors that reference nodes of the included workflow are renamed
+    /// accordingly, all others reference nodes of the including workflows.
+    /// Regex selectors only match nodes of the included workflow, they are
+    /// replaced by regex selectors that match exactly the renamed nodes. The
+    /// env of the included workflow is merged into every one of its nodes
+    /// and relative workdirs are resolved against its directory.
+    fn merge(&mut self, namespace: &str, dir: &Path, included: Workflow) -> Result<()> {
+        // declarations of the including workflow take precedence
+        for arg in included.args.into_iter().flatten() {
+            let args = self.args.get_or_insert_with(Vec::new);
+            if !args.iter().any(|v| v.name == arg.name) {
+                args.push(arg);
+            }
+        }
+
+        let local = included.nodes.keys().cloned().sorted().collect_vec();
+        let local_set = local.iter().collect::<HashSet<_>>();
+        let rename = |v: &str| format!("{}:{}", namespace, v);
+
+        for (name, mut node) in included.nodes {
+            Self::check_node_name(&name)?;
+            if let Some(pre) = node.pre.take() {
+                let mut rewritten = Vec::<NodeSelector>::new();
+                for sel in pre {
+                    match sel {
+                        | NodeSelector::Name(v) if local_set.contains(&v) => {
+                            rewritten.push(NodeSelector::Name(rename(&v)))
+                        },
+                        | NodeSelector::Name(v) => rewritten.push(NodeSelector::Name(v)),
+                        | NodeSelector::Regex(v) => {
+                            let regex = fancy_regex::Regex::new(&v)?;
+                            let mut matched = Vec::<String>::new();
+                            for n in &local {
+                                if regex.is_match(n)? {
+                                    matched.push(fancy_regex::escape(&rename(n)).into_owned());
+                                }
+                            }
+                            if !matched.is_empty() {
+                                rewritten.push(NodeSelector::Regex(format!("^(?:{})$", matched.join("|"))));
+                            }
+                        },
+                    }
+                }
+                node.pre = Some(rewritten);
+            }
+
+            node.env = Env::merge(included.env.as_ref(), node.env);
+            let rebase = |v: Option<String>| {
+                if dir.as_os_str().is_empty() {
+                    return v;
+                }
+                let path = match v {
+                    | Some(v) => dir.join(v),
+                    | None => dir.to_path_buf(),
+                };
+                Some(path.to_string_lossy().into_owned())
+            };
+            node.workdir = rebase(node.workdir);
+            for task in &mut node.tasks {
+                if task.workdir.is_some() {
+                    task.workdir = rebase(task.workdir.take());
+                }
+            }
+
+            let name = rename(&name);
+            if self.nodes.contains_key(&name) {
+                Err(anyhow::anyhow!(
+                    "included node conflicts with an existing node: {}",
+                    name
+                ))?
+            }
+            self.nodes.insert(name, node);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_version(version: &str) -> Result<()> {
         let major_minor = env!("CARGO_PKG_VERSION").split(".").take(2).join(".");
-        if &major_minor != "0.0" && &v.version != &major_minor {
+        if &major_minor != "0.0" && version != major_minor {
             // major.minor must equal
             Err(anyhow::anyhow!(
                 "workflow version {} is incompatible with this CLI version {}",
-                v.version,
+                version,
                 env!("CARGO_PKG_VERSION")
             ))?
         }
+        Ok(())
+    }
 
-        let wf: crate::workflow::Workflow = serde_yaml::from_str(&data)?;
+    pub(crate) fn check_node_name(node: &str) -> Result<()> {
         let nodes_allow_regex = fancy_regex::Regex::new(r"^[a-zA-Z0-9:_-]+$")?;
-        for node in wf.nodes.keys() {
-            if !nodes_allow_regex.is_match(node)? {
-                Err(anyhow::anyhow!("invalid node name: {}", node))?
-            }
+        if !nodes_allow_regex.is_match(node)? {
+            Err(anyhow::anyhow!("invalid node name: {}", node))?
         }
-        Ok(wf)
+        Ok(())
     }
 }
 
@@ -66,8 +273,33 @@ pub struct Env {
 }
 
 impl Env {
+    /// Merges `env` on top of `base`. Vars of `env` take precedence, capture
+    /// regexes are combined.
+    pub(crate) fn merge(base: Option<&Env>, env: Option<Env>) -> Option<Env> {
+        let base = match base {
+            | Some(v) => v.clone(),
+            | None => return env,
+        };
+        let env = match env {
+            | Some(v) => v,
+            | None => return Some(base),
+        };
+        let capture = match (base.capture, env.capture) {
+            | (Some(a), Some(b)) => Some(format!("(?:{})|(?:{})", a, b)),
+            | (a, b) => b.or(a),
+        };
+        let vars = match (base.vars, env.vars) {
+            | (Some(mut a), Some(b)) => {
+                a.extend(b);
+                Some(a)
+            },
+            | (a, b) => b.or(a),
+        };
+        Some(Env { capture, vars })
+    }
+
     pub(crate) fn compile(&self) -> Result<HashMap<String, String>> {
-        let mut map = self.vars.clone().or(Some(HashMap::<_, _>::new())).unwrap();
+        let mut map = self.vars.clone().unwrap_or_default();
         match &self.capture {
             | Some(v) => {
                 let regex = fancy_regex::Regex::new(v)?;
@@ -84,6 +316,115 @@ impl Env {
     }
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+/// Declaration of an argument for handlebars placeholders.
+pub(crate) struct Argument {
+    /// Path of the placeholder as passed to `-a` (e.g. `args.env`).
+    pub name: String,
+    /// A description of this argument.
+    pub description: Option<String>,
+    /// The type the value is converted to. Defaults to `string`.
+    #[serde(rename = "type", default)]
+    pub kind: ArgumentType,
+    /// Allowed values of an `enum` argument.
+    pub values: Option<Vec<String>>,
+    /// Value used if the argument is not passed.
+    pub default: Option<serde_json::Value>,
+    /// Fails the planning of nodes that use this argument if it is neither
+    /// passed nor defaulted.
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl Argument {
+    /// Validates the value and converts it to the declared type.
+    pub(crate) fn coerce(&self, value: &str) -> Result<serde_json::Value> {
+        let invalid = || anyhow::anyhow!("invalid value for argument {} ({}): {}", self.name, self.kind, value);
+        Ok(match self.kind {
+            | ArgumentType::String => serde_json::Value::String(value.to_owned()),
+            | ArgumentType::Int => serde_json::Value::from(value.parse::<i64>().map_err(|_| invalid())?),
+            | ArgumentType::Bool => serde_json::Value::Bool(value.parse::<bool>().map_err(|_| invalid())?),
+            | ArgumentType::Enum => {
+                let values = self.values.clone().unwrap_or_default();
+                if !values.iter().any(|v| v == value) {
+                    Err(anyhow::anyhow!(
+                        "invalid value for argument {}: {} (expected one of: {})",
+                        self.name,
+                        value,
+                        values.join(", ")
+                    ))?
+                }
+                serde_json::Value::String(value.to_owned())
+            },
+        })
+    }
+
+    /// The default value as it would be passed to `-a`.
+    pub(crate) fn default_value(&self) -> Option<String> {
+        self.default.as_ref().map(|v| {
+            match v {
+                | serde_json::Value::String(v) => v.clone(),
+                | v => v.to_string(),
+            }
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ArgumentType {
+    #[default]
+    String,
+    Int,
+    Bool,
+    Enum,
+}
+
+impl std::fmt::Display for ArgumentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            | ArgumentType::String => write!(f, "string"),
+            | ArgumentType::Int => write!(f, "int"),
+            | ArgumentType::Bool => write!(f, "bool"),
+            | ArgumentType::Enum => write!(f, "enum"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+/// Another workflow file to include.
+pub(crate) struct Include {
+    /// Path of the workflow file relative to this one. Can be a glob pattern.
+    pub path: String,
+    /// Prefix of the included nodes (`<namespace>:<node>`). Defaults to the
+    /// name of the directory that contains the included workflow.
+    pub namespace: Option<String>,
+}
+
+impl Include {
+    /// Finds the included workflow files relative to the directory of the
+    /// including workflow, sorted by their path.
+    pub(crate) fn files(&self, base: &Path) -> Result<Vec<PathBuf>> {
+        let pattern = base.join(&self.path);
+        let pattern = pattern
+            .to_str()
+            .ok_or(anyhow::anyhow!("invalid include path: {}", self.path))?;
+        let files = glob::glob(pattern)
+            .map_err(|e| anyhow::anyhow!("invalid include path {}: {}", self.path, e))?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .sorted()
+            .collect_vec();
+        // a glob may match nothing (yet), a plain path must exist
+        if files.is_empty() && !self.path.contains(['*', '?', '[']) {
+            Err(anyhow::anyhow!("included workflow not found: {}", self.path))?
+        }
+        Ok(files)
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub(crate) enum NodeSelector {
@@ -91,12 +432,56 @@ pub(crate) enum NodeSelector {
     Regex(String),
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
+/// Either the name of a template or the name together with the position of
+/// its tasks.
+pub(crate) enum Extends {
+    Template(String),
+    Options(ExtendsOptions),
+}
+
+impl Extends {
+    pub(crate) fn template(&self) -> &str {
+        match self {
+            | Extends::Template(v) => v,
+            | Extends::Options(v) => &v.template,
+        }
+    }
+
+    pub(crate) fn tasks(&self) -> TemplateTasks {
+        match self {
+            | Extends::Template(_) => TemplateTasks::Prepend,
+            | Extends::Options(v) => v.tasks.clone().unwrap_or(TemplateTasks::Prepend),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) struct ExtendsOptions {
+    /// Name of the template.
+    pub template: String,
+    /// Where the tasks of the template are put. Defaults to `prepend`.
+    pub tasks: Option<TemplateTasks>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+/// Position of the tasks of a template relative to the tasks of the node.
+pub(crate) enum TemplateTasks {
+    Prepend,
+    Append,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 /// An individual node for executing a task batch.
 pub(crate) struct Node {
     /// A description of this node.
     pub description: Option<String>,
+    /// Template this node is based on.
+    pub extends: Option<Extends>,
     /// Reference nodes that need to be executed prior to this one.
     pub pre: Option<Vec<NodeSelector>>,
 
@@ -104,7 +489,15 @@ pub(crate) struct Node {
     /// product.
     pub matrix: Option<Matrix>,
     /// The tasks to be executed.
+    #[serde(default)]
     pub tasks: Vec<Task>,
+    /// Glob patterns of the files read by the tasks (relative to the workdir).
+    /// A node with inputs is skipped if neither its inputs nor its tasks
+    /// changed since its last successful execution.
+    pub inputs: Option<Vec<String>>,
+    /// Glob patterns of the files produced by the tasks (relative to the
+    /// workdir). A node is only up to date if every pattern matches.
+    pub outputs: Option<Vec<String>>,
 
     /// Env vars.
     pub env: Option<Env>,
@@ -112,6 +505,61 @@ pub(crate) struct Node {
     pub shell: Option<String>,
     /// Custom workdir.
     pub workdir: Option<String>,
+    /// Default retry policy for all tasks of this node.
+    pub retry: Option<Retry>,
+    /// Treats failures of the tasks of this node as warnings.
+    pub allow_failure: Option<AllowFailure>,
+    /// Maximum runtime of the whole node (e.g. "90s", "5m").
+    #[serde(default, with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub timeout: Option<Duration>,
+}
+
+impl Node {
+    /// Merges this node on top of the given (expanded) template. Env vars are
+    /// merged, matrix dimensions and prerequisites are concatenated and tasks
+    /// are put in the given order. All other settings of the node take
+    /// precedence over the ones of the template.
+    pub(crate) fn extend(self, template: &Node, tasks: TemplateTasks) -> Node {
+        let template = template.clone();
+        fn concat<T>(a: Option<Vec<T>>, b: Option<Vec<T>>) -> Option<Vec<T>> {
+            match (a, b) {
+                | (Some(mut a), Some(b)) => {
+                    a.extend(b);
+                    Some(a)
+                },
+                | (a, b) => b.or(a),
+            }
+        }
+        Node {
+            description: self.description.or(template.description),
+            extends: None,
+            pre: concat(template.pre, self.pre),
+            matrix: match (template.matrix, self.matrix) {
+                | (Some(a), Some(b)) => {
+                    Some(Matrix {
+                        parallel: b.parallel,
+                        dimensions: a.dimensions.into_iter().chain(b.dimensions).collect(),
+                        exclude: concat(a.exclude, b.exclude),
+                        include: concat(a.include, b.include),
+                    })
+                },
+                | (a, b) => b.or(a),
+            },
+            tasks: match tasks {
+                | TemplateTasks::Prepend => template.tasks.into_iter().chain(self.tasks).collect(),
+                | TemplateTasks::Append => self.tasks.into_iter().chain(template.tasks).collect(),
+            },
+            inputs: self.inputs.or(template.inputs),
+            outputs: self.outputs.or(template.outputs),
+            env: Env::merge(template.env.as_ref(), self.env),
+            shell: self.shell.or(template.shell),
+            workdir: self.workdir.or(template.workdir),
+            retry: self.retry.or(template.retry),
+            allow_failure: self.allow_failure.or(template.allow_failure),
+            timeout: self.timeout.or(template.timeout),
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
@@ -120,6 +568,12 @@ pub(crate) struct Node {
 pub(crate) struct Matrix {
     pub parallel: bool,
     pub dimensions: Vec<Vec<MatrixCell>>,
+    /// Removes every entry of the cartesian product that matches any of the
+    /// rules.
+    pub exclude: Option<Vec<MatrixMatch>>,
+    /// Additional entries that are added after the exclusion. They lie outside
+    /// of the grid, each of their coordinates continues its dimension.
+    pub include: Option<Vec<MatrixCell>>,
 }
 
 impl Matrix {
@@ -136,31 +590,106 @@ impl Matrix {
                 .collect_vec()
         });
 
-        let cp = dims_widx.multi_cartesian_product();
+        // without dimensions, the product consists of one empty entry which is
+        // only kept if nothing is included
+        let includes = self.include.as_deref().unwrap_or_default();
+        let cp = dims_widx
+            .multi_cartesian_product()
+            .filter(|_| !self.dimensions.is_empty() || includes.is_empty());
         let mut v = Vec::<crate::plan::Invocation>::new();
 
         for next in cp {
             let cell = next.iter().map(|v| v.0).collect::<Vec<_>>();
 
             let mut env = HashMap::<String, String>::new();
+            let mut values = HashMap::<String, String>::new();
             for m in next {
                 if let Some(e) = &m.1.env {
                     env.extend(e.compile()?);
                 }
+                if let Some(vals) = &m.1.values {
+                    values.extend(vals.clone());
+                }
             }
 
-            v.push(crate::plan::Invocation { env, cell });
+            let invocation = crate::plan::Invocation { cell, values, env };
+            if !self.exclude.iter().flatten().any(|v| v.matches(&invocation)) {
+                v.push(invocation);
+            }
+        }
+
+        // every coordinate of an included entry continues its dimension, so none
+        // of them is shared with an entry of the product
+        let lens = match self.dimensions.len() {
+            | 0 => vec![0],
+            | _ => self.dimensions.iter().map(|v| v.len()).collect_vec(),
+        };
+        for (idx, include) in includes.iter().enumerate() {
+            let cell = lens
+                .iter()
+                .map(|len| u8::try_from(len + idx).ok())
+                .collect::<Option<Vec<_>>>()
+                .ok_or(anyhow::anyhow!(
+                    "too many matrix entries in a dimension (at most {} including includes)",
+                    u8::MAX as usize + 1
+                ))?;
+            v.push(crate::plan::Invocation {
+                cell,
+                values: include.values.clone().unwrap_or_default(),
+                env: match &include.env {
+                    | Some(e) => e.compile()?,
+                    | None => HashMap::new(),
+                },
+            });
         }
         Ok(v)
     }
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+/// Matches the entries of a matrix whose env vars and values contain all of
+/// the given ones.
+pub(crate) struct MatrixMatch {
+    /// Env vars of the entry (including captured ones).
+    pub env: Option<MatrixMatchEnv>,
+    pub values: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+/// The env vars an entry of a matrix needs to contain, shaped like `Env`.
+pub(crate) struct MatrixMatchEnv {
+    pub vars: Option<HashMap<String, String>>,
+}
+
+impl MatrixMatch {
+    pub(crate) fn matches(&self, invocation: &crate::plan::Invocation) -> bool {
+        let contains = |expected: Option<&HashMap<String, String>>, actual: &HashMap<String, String>| {
+            expected.into_iter().flatten().all(|(k, v)| actual.get(k) == Some(v))
+        };
+        contains(self.env.as_ref().and_then(|v| v.vars.as_ref()), &invocation.env)
+            && contains(self.values.as_ref(), &invocation.values)
+    }
+
+    /// Whether the rule does not constrain anything and matches every entry.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.env
+            .as_ref()
+            .and_then(|v| v.vars.as_ref())
+            .is_none_or(|v| v.is_empty())
+            && self.values.as_ref().is_none_or(|v| v.is_empty())
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 /// An entry in the n-dimensional matrix for the node execution.
 pub(crate) struct MatrixCell {
     /// Environment variables.
     pub env: Option<Env>,
+    /// Named values, available as `matrix.<name>` in scripts.
+    pub values: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
@@ -176,4 +705,80 @@ pub(crate) struct Task {
     pub shell: Option<String>,
     /// Custom workdir.
     pub workdir: Option<String>,
+    /// Maximum runtime of this task per matrix entry (e.g. "90s", "5m").
+    #[serde(default, with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub timeout: Option<Duration>,
+    /// Retry policy for failed tasks.
+    pub retry: Option<Retry>,
+    /// Treats failures of this task as warnings.
+    pub allow_failure: Option<AllowFailure>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+/// Retry policy for a task. Applies to every matrix entry individually.
+pub(crate) struct Retry {
+    /// Maximum number of attempts (including the first one).
+    pub attempts: u32,
+    /// Delay before the first retry (e.g. "500ms", "5s").
+    #[serde(default, with = "humantime_serde")]
+    #[schemars(with = "Option<String>")]
+    pub delay: Option<Duration>,
+    /// Factor by which the delay is multiplied after every retry.
+    pub backoff: Option<f64>,
+    /// Exit codes that are retried. If not set, every failure is retried.
+    pub exit_codes: Option<Vec<i32>>,
+}
+
+impl Retry {
+    /// Upper bound for the delay between two attempts.
+    pub(crate) const MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+    /// Checks that the policy allows at least one attempt and that the
+    /// backoff factor is a finite, non-negative number.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.attempts == 0 {
+            Err(anyhow::anyhow!("attempts must be at least 1"))?
+        }
+        if let Some(v) = self.backoff {
+            if !v.is_finite() || v < 0.0 {
+                Err(anyhow::anyhow!("backoff must be a finite, non-negative number: {}", v))?
+            }
+        }
+        Ok(())
+    }
+
+    /// The delay before the next attempt after `failed` failed attempts. The
+    /// delay never exceeds `MAX_DELAY`.
+    pub(crate) fn delay_before(&self, failed: usize) -> Duration {
+        let delay = self.delay.unwrap_or_default();
+        let factor = match self.backoff {
+            | Some(v) => v.powi(failed.saturating_sub(1).min(i32::MAX as usize) as i32),
+            | None => 1.0,
+        };
+        Duration::try_from_secs_f64(delay.as_secs_f64() * factor)
+            .unwrap_or(Self::MAX_DELAY)
+            .min(Self::MAX_DELAY)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
+/// Either allows every failure (`true`) or only the given exit codes.
+pub(crate) enum AllowFailure {
+    All(bool),
+    ExitCodes(Vec<i32>),
+}
+
+impl AllowFailure {
+    /// Whether a failure with the given exit code is allowed. `None` stands for
+    /// failures without an exit code (signals, timeouts).
+    pub(crate) fn allows(&self, code: Option<i32>) -> bool {
+        match (self, code) {
+            | (AllowFailure::All(v), _) => *v,
+            | (AllowFailure::ExitCodes(v), Some(code)) => v.contains(&code),
+            | (AllowFailure::ExitCodes(_), None) => false,
+        }
+    }
 }